@@ -0,0 +1,330 @@
+//! Per-resource access control.
+//!
+//! An `Acl` is a list of rules granting a right (`read` < `write` <
+//! `admin`) on every resource under a URL prefix to a peer, or to anyone.
+//! A peer's right on a resource is the highest right any matching rule
+//! grants it. The node's own identity is always `admin`.
+//!
+//! Rights are checked for HTTP/3 `GET`s and `PUT`s (against the peer at the
+//! other end of the QUIC connection), for gossip updates and for history
+//! fetched by catch-up sync (against the update's verified author).
+//!
+//! The ACL is itself a Braid resource at `ACL_URL`, holding the JSON form
+//! of an `Acl`. Writing it goes through the normal PUT / gossip path, so
+//! ACL changes are versioned and propagate to peers subscribed to it. A
+//! new ACL is only accepted if the writer is `admin` on the prefix of
+//! every rule it adds or removes.
+
+use iroh::EndpointId;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::error::{BraidIrohError, Result};
+use crate::subscription::SubscriptionManager;
+
+/// URL of the resource holding the node's ACL.
+pub const ACL_URL: &str = "/.well-known/braid-acl";
+
+/// What a rule allows. Each right includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Right {
+    /// GET and subscribe.
+    Read,
+    /// PUT new versions.
+    Write,
+    /// Change ACL rules for the prefix.
+    Admin,
+}
+
+/// Who a rule applies to. Written as `"*"` or an endpoint ID in JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Principal {
+    /// Every peer, including anonymous HTTP clients.
+    Anyone,
+    /// One iroh endpoint.
+    Peer(EndpointId),
+}
+
+impl Principal {
+    fn matches(&self, peer: Option<&EndpointId>) -> bool {
+        match self {
+            Principal::Anyone => true,
+            Principal::Peer(id) => peer == Some(id),
+        }
+    }
+}
+
+impl fmt::Display for Principal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Principal::Anyone => write!(f, "*"),
+            Principal::Peer(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl From<Principal> for String {
+    fn from(principal: Principal) -> Self {
+        principal.to_string()
+    }
+}
+
+impl TryFrom<String> for Principal {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, String> {
+        if value == "*" {
+            return Ok(Principal::Anyone);
+        }
+        value
+            .parse()
+            .map(Principal::Peer)
+            .map_err(|_| format!("invalid peer id: {}", value))
+    }
+}
+
+/// One grant: `principal` has `right` on every resource under `prefix`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AclRule {
+    pub prefix: String,
+    pub peer: Principal,
+    pub right: Right,
+}
+
+/// A set of access rules.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Acl {
+    pub rules: Vec<AclRule>,
+}
+
+impl Acl {
+    /// An ACL granting nothing to anyone but the node itself.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Anyone may read and write every resource; only the node may change
+    /// the ACL. This matches a node without access control.
+    pub fn open() -> Self {
+        Self::new().allow("/", Principal::Anyone, Right::Write)
+    }
+
+    /// Add a rule.
+    pub fn allow(mut self, prefix: &str, peer: Principal, right: Right) -> Self {
+        self.rules.push(AclRule {
+            prefix: SubscriptionManager::normalize_url(prefix),
+            peer,
+            right,
+        });
+        self
+    }
+
+    /// The highest right any rule grants `peer` on `url`. `None` stands for
+    /// an anonymous peer.
+    pub fn right_for(&self, peer: Option<&EndpointId>, url: &str) -> Option<Right> {
+        let url = SubscriptionManager::normalize_url(url);
+        self.rules
+            .iter()
+            .filter(|rule| rule.peer.matches(peer) && prefix_matches(&rule.prefix, &url))
+            .map(|rule| rule.right)
+            .max()
+    }
+
+    /// `right_for`, counting only rules that name `peer` itself; `*` rules
+    /// are ignored. An anonymous peer has no such rules.
+    pub fn granted_right(&self, peer: Option<&EndpointId>, url: &str) -> Option<Right> {
+        let url = SubscriptionManager::normalize_url(url);
+        self.rules
+            .iter()
+            .filter(|rule| matches!(rule.peer, Principal::Peer(id) if Some(&id) == peer))
+            .filter(|rule| prefix_matches(&rule.prefix, &url))
+            .map(|rule| rule.right)
+            .max()
+    }
+}
+
+/// Whether `url` is `prefix` or lies under it, by whole path segments.
+fn prefix_matches(prefix: &str, url: &str) -> bool {
+    let prefix = SubscriptionManager::normalize_url(prefix);
+    prefix == "/"
+        || url == prefix
+        || (url.starts_with(&prefix) && url.as_bytes().get(prefix.len()) == Some(&b'/'))
+}
+
+/// The live ACL of a node, shared by the handlers and the gossip ingest.
+#[derive(Clone)]
+pub struct AclManager {
+    owner: EndpointId,
+    acl: Arc<RwLock<Acl>>,
+}
+
+impl AclManager {
+    /// Enforce `acl` on a node whose identity is `owner`.
+    pub fn new(owner: EndpointId, acl: Acl) -> Self {
+        Self {
+            owner,
+            acl: Arc::new(RwLock::new(acl)),
+        }
+    }
+
+    /// A copy of the current rules.
+    pub fn current(&self) -> Acl {
+        self.acl.read().clone()
+    }
+
+    /// Whether `peer` has at least `right` on `url`.
+    pub fn allows(&self, peer: Option<&EndpointId>, url: &str, right: Right) -> bool {
+        peer == Some(&self.owner) || self.acl.read().right_for(peer, url) >= Some(right)
+    }
+
+    /// Whether a rule naming `peer` grants it at least `right` on `url`.
+    /// Used for private resources, which `*` rules don't open up.
+    pub fn grants(&self, peer: Option<&EndpointId>, url: &str, right: Right) -> bool {
+        peer == Some(&self.owner) || self.acl.read().granted_right(peer, url) >= Some(right)
+    }
+
+    /// `allows`, as a `Forbidden` error.
+    pub fn check(&self, peer: Option<&EndpointId>, url: &str, right: Right) -> Result<()> {
+        if self.allows(peer, url, right) {
+            Ok(())
+        } else {
+            Err(forbidden(peer, &format!("{:?} on {}", right, url)))
+        }
+    }
+
+    /// `grants`, as a `Forbidden` error.
+    pub fn check_granted(&self, peer: Option<&EndpointId>, url: &str, right: Right) -> Result<()> {
+        if self.grants(peer, url, right) {
+            Ok(())
+        } else {
+            Err(forbidden(
+                peer,
+                &format!("an explicit {:?} grant on {}", right, url),
+            ))
+        }
+    }
+
+    /// Check that `peer` may replace the current ACL with `proposed`: it
+    /// must be `admin` on the prefix of every rule added or removed.
+    pub fn check_change(&self, peer: Option<&EndpointId>, proposed: &Acl) -> Result<()> {
+        let current = self.current();
+        let added = proposed.rules.iter().filter(|r| !current.rules.contains(r));
+        let removed = current.rules.iter().filter(|r| !proposed.rules.contains(r));
+        for rule in added.chain(removed) {
+            if !self.allows(peer, &rule.prefix, Right::Admin) {
+                return Err(forbidden(peer, &format!("Admin on {}", rule.prefix)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the rules in force.
+    pub fn replace(&self, acl: Acl) {
+        *self.acl.write() = acl;
+    }
+}
+
+fn forbidden(peer: Option<&EndpointId>, needed: &str) -> BraidIrohError {
+    let who = peer
+        .map(|p| p.to_string())
+        .unwrap_or_else(|| "anonymous peer".to_string());
+    BraidIrohError::Forbidden(format!("{} lacks {}", who, needed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(seed: u8) -> EndpointId {
+        iroh::SecretKey::from_bytes(&[seed; 32]).public()
+    }
+
+    #[test]
+    fn test_prefix_matches_whole_segments() {
+        assert!(prefix_matches("/", "/anything"));
+        assert!(prefix_matches("/docs", "/docs"));
+        assert!(prefix_matches("/docs/", "/docs/team/notes"));
+        assert!(!prefix_matches("/docs", "/docsx"));
+        assert!(!prefix_matches("/docs/team", "/docs"));
+    }
+
+    #[test]
+    fn test_highest_matching_right_wins() {
+        let alice = peer(1);
+        let acl = Acl::new().allow("/", Principal::Anyone, Right::Read).allow(
+            "/docs",
+            Principal::Peer(alice),
+            Right::Write,
+        );
+
+        assert_eq!(acl.right_for(None, "/docs/a"), Some(Right::Read));
+        assert_eq!(acl.right_for(Some(&alice), "/docs/a"), Some(Right::Write));
+        assert_eq!(acl.right_for(Some(&alice), "/other"), Some(Right::Read));
+        assert_eq!(Acl::new().right_for(Some(&alice), "/docs"), None);
+    }
+
+    #[test]
+    fn test_granted_right_ignores_anyone_rules() {
+        let (owner, alice) = (peer(1), peer(2));
+        let acls = AclManager::new(
+            owner,
+            Acl::open().allow("/team", Principal::Peer(alice), Right::Read),
+        );
+        assert!(acls.allows(Some(&peer(3)), "/team/notes", Right::Read));
+        assert!(!acls.grants(Some(&peer(3)), "/team/notes", Right::Read));
+        assert!(!acls.grants(None, "/team/notes", Right::Read));
+        assert!(acls.grants(Some(&alice), "/team/notes", Right::Read));
+        assert!(!acls.grants(Some(&alice), "/team/notes", Right::Write));
+        assert!(acls.grants(Some(&owner), "/team/notes", Right::Admin));
+    }
+
+    #[test]
+    fn test_owner_is_always_admin() {
+        let owner = peer(1);
+        let acls = AclManager::new(owner, Acl::new());
+        assert!(acls.allows(Some(&owner), "/x", Right::Admin));
+        assert!(!acls.allows(Some(&peer(2)), "/x", Right::Read));
+        assert!(matches!(
+            acls.check(None, "/x", Right::Read),
+            Err(BraidIrohError::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn test_acl_change_needs_admin_on_touched_prefixes() {
+        let (owner, alice, bob) = (peer(1), peer(2), peer(3));
+        let acls = AclManager::new(
+            owner,
+            Acl::open().allow("/team", Principal::Peer(alice), Right::Admin),
+        );
+
+        let grant_team = acls
+            .current()
+            .allow("/team/notes", Principal::Peer(bob), Right::Write);
+        assert!(acls.check_change(Some(&alice), &grant_team).is_ok());
+        assert!(acls.check_change(Some(&bob), &grant_team).is_err());
+
+        let grant_root = acls
+            .current()
+            .allow("/", Principal::Peer(bob), Right::Admin);
+        assert!(acls.check_change(Some(&alice), &grant_root).is_err());
+        assert!(acls.check_change(Some(&owner), &grant_root).is_ok());
+    }
+
+    #[test]
+    fn test_acl_json_roundtrip() {
+        let acl = Acl::open().allow("/team", Principal::Peer(peer(2)), Right::Admin);
+        let json = serde_json::to_string(&acl).unwrap();
+        assert!(json.contains("\"peer\":\"*\""));
+        assert!(json.contains("\"right\":\"admin\""));
+        assert_eq!(serde_json::from_str::<Acl>(&json).unwrap(), acl);
+        assert!(serde_json::from_str::<Acl>(
+            r#"{"rules":[{"prefix":"/","peer":"bogus","right":"read"}]}"#
+        )
+        .is_err());
+    }
+}
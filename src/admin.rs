@@ -0,0 +1,234 @@
+//! Local admin API.
+//!
+//! JSON routes under `/_braid/` for inspecting and steering a running node
+//! from the machine it runs on. The proxy mounts them on its listener when
+//! `ProxyConfig::admin_token` is set; every request must then carry
+//! `Authorization: Bearer <token>` or gets 401.
+//!
+//! - `GET /_braid/node`: the node's ID, address and discovery state.
+//! - `GET /_braid/topics`: joined topics and their gossip neighbors.
+//! - `POST /_braid/topics`: join a topic, `{"url": ..., "peers": [...]}`.
+//! - `DELETE /_braid/topics/<url>`: leave a topic.
+//! - `GET /_braid/resources`: stored resources and their version counts.
+//!
+//! `/_braid/metrics` stays open, for scrapers.
+
+use axum::{
+    extract::{Path, Request, State},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use http::{header, HeaderMap, StatusCode};
+use iroh::{Endpoint, EndpointAddr, EndpointId};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::discovery::DiscoveryConfig;
+use crate::error::{BraidIrohError, Result};
+use crate::node::TopicControl;
+use crate::protocol::BraidAppState;
+use crate::subscription::SubscriptionManager;
+
+/// What the admin routes need from the node.
+#[derive(Clone)]
+pub struct AdminState {
+    /// Bearer token every request must present.
+    pub token: Arc<str>,
+    pub endpoint: Endpoint,
+    pub discovery: DiscoveryConfig,
+    pub state: BraidAppState,
+    pub topics: TopicControl,
+}
+
+/// Response of `GET /_braid/node`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeInfo {
+    pub id: EndpointId,
+    pub addr: EndpointAddr,
+    /// `"mock"` or `"real"`.
+    pub discovery: String,
+    /// Peers registered in the mock discovery map; empty for real
+    /// discovery.
+    pub known_peers: Vec<EndpointId>,
+}
+
+/// One entry of `GET /_braid/topics`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TopicInfo {
+    pub url: String,
+    /// Whether the topic's gossip is sealed with a `ResourceSecret`.
+    pub private: bool,
+    pub neighbors: Vec<EndpointId>,
+}
+
+/// Body of `POST /_braid/topics`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JoinRequest {
+    pub url: String,
+    /// Peers to join through.
+    #[serde(default)]
+    pub peers: Vec<EndpointId>,
+}
+
+/// One entry of `GET /_braid/resources`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResourceInfo {
+    pub url: String,
+    pub versions: usize,
+    pub frontier: Vec<String>,
+}
+
+/// The admin routes, behind the token check.
+pub fn router(state: AdminState) -> Router {
+    Router::new()
+        .route("/_braid/node", get(node_info))
+        .route("/_braid/topics", get(list_topics).post(join_topic))
+        .route("/_braid/topics/{*url}", delete(leave_topic))
+        .route("/_braid/resources", get(list_resources))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+async fn require_token(State(state): State<AdminState>, req: Request, next: Next) -> Response {
+    if !authorized(req.headers(), &state.token) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "missing or wrong admin token",
+        )
+            .into_response();
+    }
+    next.run(req).await
+}
+
+/// Check a configured admin token: an empty or blank one would let
+/// `Authorization: Bearer ` through.
+pub fn check_token(token: &str) -> Result<()> {
+    if token.trim().is_empty() {
+        return Err(BraidIrohError::Proxy(
+            "admin token must not be empty".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the request carries `Authorization: Bearer <token>`. Never true
+/// for an empty token.
+pub fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    check_token(token).is_ok()
+        && headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| constant_time_eq(given.trim().as_bytes(), token.as_bytes()))
+}
+
+/// Compare without returning early, so response timing doesn't reveal how
+/// much of the token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn node_info(State(state): State<AdminState>) -> Json<NodeInfo> {
+    let (discovery, known_peers) = match &state.discovery {
+        DiscoveryConfig::Mock(map) => ("mock", map.peers()),
+        DiscoveryConfig::Real => ("real", Vec::new()),
+    };
+    Json(NodeInfo {
+        id: state.endpoint.id(),
+        addr: state.endpoint.addr(),
+        discovery: discovery.to_string(),
+        known_peers,
+    })
+}
+
+async fn list_topics(State(state): State<AdminState>) -> Json<Vec<TopicInfo>> {
+    let subscriptions = &state.state.subscriptions;
+    let mut topics = Vec::new();
+    for url in subscriptions.active_topics().await {
+        topics.push(TopicInfo {
+            private: subscriptions.secret(&url).is_some(),
+            neighbors: subscriptions.neighbors(&url).await.unwrap_or_default(),
+            url,
+        });
+    }
+    Json(topics)
+}
+
+async fn join_topic(
+    State(state): State<AdminState>,
+    Json(request): Json<JoinRequest>,
+) -> Result<StatusCode> {
+    let url = SubscriptionManager::normalize_url(&request.url);
+    tracing::info!(url = %url, peers = ?request.peers, "admin: joining topic");
+    // Updates reach the store through the ingest task; the stream isn't needed.
+    let _updates = state.topics.join(&url, request.peers).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn leave_topic(
+    State(state): State<AdminState>,
+    Path(url): Path<String>,
+) -> Result<StatusCode> {
+    let url = SubscriptionManager::normalize_url(&url);
+    tracing::info!(url = %url, "admin: leaving topic");
+    if state.topics.leave(&url).await {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(BraidIrohError::TopicNotFound(url))
+    }
+}
+
+async fn list_resources(State(state): State<AdminState>) -> Json<Vec<ResourceInfo>> {
+    let store = &state.state.store;
+    let mut urls = store.list();
+    urls.sort();
+    let resources = urls
+        .into_iter()
+        .map(|url| ResourceInfo {
+            versions: store.graph(&url).map_or(0, |graph| graph.len()),
+            frontier: store.frontier(&url),
+            url,
+        })
+        .collect();
+    Json(resources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn with_auth(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_bearer_token_is_required() {
+        assert!(authorized(&with_auth("Bearer s3cret"), "s3cret"));
+        assert!(!authorized(&with_auth("Bearer s3cre"), "s3cret"));
+        assert!(!authorized(&with_auth("Bearer s3cretx"), "s3cret"));
+        assert!(!authorized(&with_auth("Basic s3cret"), "s3cret"));
+        assert!(!authorized(&HeaderMap::new(), "s3cret"));
+    }
+
+    #[test]
+    fn test_empty_token_authorizes_nothing() {
+        assert!(check_token("").is_err());
+        assert!(check_token(" \t").is_err());
+        assert!(check_token("s3cret").is_ok());
+        assert!(!authorized(&with_auth("Bearer "), ""));
+        assert!(!authorized(&with_auth("Bearer  "), " "));
+    }
+
+    #[test]
+    fn test_join_request_peers_default_to_empty() {
+        let request: JoinRequest = serde_json::from_str(r#"{"url": "/doc"}"#).unwrap();
+        assert_eq!(request.url, "/doc");
+        assert!(request.peers.is_empty());
+    }
+}
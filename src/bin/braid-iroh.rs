@@ -0,0 +1,357 @@
+//! `braid-iroh`: run a node, or talk to one.
+//!
+//! `run` starts a node whose identity and history live in `--data-dir`.
+//! `get`, `put`, `history` and `subscribe` send Braid requests over HTTP/3
+//! from a throwaway identity to `--peer`, or to the node in `--data-dir`
+//! when no peer is given, so the node must be running and reachable through
+//! discovery (`run --discovery real`).
+
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use braid_iroh::{
+    codec, BraidIrohNode, BraidTicket, DiscoveryConfig, Keystore, StorageConfig, BRAID_H3_ALPN,
+};
+use bytes::Bytes;
+use clap::{Parser, Subcommand, ValueEnum};
+use futures::StreamExt;
+use http::{Method, StatusCode};
+use iroh::{Endpoint, EndpointId};
+use iroh_h3_client::IrohH3Client;
+use tracing_subscriber::EnvFilter;
+
+type CliResult<T = ()> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(Parser)]
+#[command(name = "braid-iroh", version, about = "Braid-HTTP over iroh")]
+struct Cli {
+    /// Where the node keeps its key and history.
+    #[arg(long, global = true, default_value = ".braid-iroh")]
+    data_dir: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a node until interrupted.
+    Run {
+        /// Address for the HTTP/1.1 proxy.
+        #[arg(long, default_value = "127.0.0.1:8080", conflicts_with = "no_proxy")]
+        proxy: SocketAddr,
+        /// Don't start the proxy.
+        #[arg(long)]
+        no_proxy: bool,
+        /// Serve the admin API under `/_braid/` on the proxy, for requests
+        /// with `Authorization: Bearer <TOKEN>`.
+        #[arg(long, value_name = "TOKEN", conflicts_with = "no_proxy", value_parser = admin_token)]
+        admin_token: Option<String>,
+        /// How to find peers.
+        #[arg(long, value_enum, default_value_t = Discovery::Real)]
+        discovery: Discovery,
+        /// Keep history in memory instead of under the data dir.
+        #[arg(long)]
+        memory: bool,
+        /// Subscribe to a resource on startup (repeatable).
+        #[arg(long = "subscribe", value_name = "URL")]
+        subscriptions: Vec<String>,
+        /// Redeem a ticket on startup (repeatable).
+        #[arg(long = "join", value_name = "TICKET")]
+        tickets: Vec<BraidTicket>,
+    },
+    /// Print the node's address, and a ticket for a resource. Starts the
+    /// node briefly, so use it while the node isn't running; `run` prints
+    /// the same on startup.
+    Addr {
+        /// Also print a ticket for this resource.
+        #[arg(long, value_name = "URL")]
+        ticket: Option<String>,
+    },
+    /// Fetch a resource (or one version of it).
+    Get {
+        url: String,
+        #[arg(long)]
+        version: Option<String>,
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Write a new version of a resource. Reads the body from stdin if none
+    /// is given or it is `-`.
+    Put {
+        url: String,
+        body: Option<String>,
+        #[arg(long, default_value = "text/plain")]
+        content_type: String,
+        #[command(flatten)]
+        target: Target,
+    },
+    /// List a resource's version IDs, parents before children.
+    History {
+        url: String,
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Print every update to a resource as it arrives.
+    Subscribe {
+        url: String,
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Switch the node in the data dir to a fresh key and print the signed
+    /// `KeyRotation` statement, as JSON, for peers that knew the old one.
+    /// Takes effect when the node is next started.
+    Rotate,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Discovery {
+    /// DNS, Pkarr and mDNS.
+    Real,
+    /// In-process only; for trying things out alone.
+    Mock,
+}
+
+#[derive(clap::Args)]
+struct Target {
+    /// Peer to ask; defaults to the node in the data dir.
+    #[arg(long)]
+    peer: Option<EndpointId>,
+    /// Print the response status and headers to stderr.
+    #[arg(long, short = 'i')]
+    include: bool,
+}
+
+/// `--admin-token` must not be empty: an empty token would accept
+/// `Authorization: Bearer `.
+fn admin_token(value: &str) -> Result<String, String> {
+    braid_iroh::admin::check_token(value)
+        .map(|()| value.to_string())
+        .map_err(|e| e.to_string())
+}
+
+#[tokio::main]
+async fn main() -> CliResult {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Run {
+            proxy,
+            no_proxy,
+            admin_token,
+            discovery,
+            memory,
+            subscriptions,
+            tickets,
+        } => {
+            run(
+                cli.data_dir,
+                (!no_proxy).then_some(proxy),
+                admin_token,
+                discovery,
+                memory,
+                subscriptions,
+                tickets,
+            )
+            .await
+        }
+        Command::Addr { ticket } => addr(cli.data_dir, ticket).await,
+        Command::Get {
+            url,
+            version,
+            target,
+        } => {
+            let path = match version {
+                Some(version) => codec::resource_uri(&url, &[("version", &version)]),
+                None => codec::resource_uri(&url, &[]),
+            };
+            let body = request(&cli.data_dir, &target, Method::GET, &path, &[], None).await?;
+            std::io::stdout().write_all(&body)?;
+            Ok(())
+        }
+        Command::Put {
+            url,
+            body,
+            content_type,
+            target,
+        } => {
+            let body = match body.as_deref() {
+                None | Some("-") => {
+                    let mut buf = Vec::new();
+                    std::io::stdin().read_to_end(&mut buf)?;
+                    Bytes::from(buf)
+                }
+                Some(body) => Bytes::from(body.to_string()),
+            };
+            let headers = [("content-type", content_type.as_str())];
+            request(
+                &cli.data_dir,
+                &target,
+                Method::PUT,
+                &codec::resource_uri(&url, &[]),
+                &headers,
+                Some(body),
+            )
+            .await?;
+            Ok(())
+        }
+        Command::History { url, target } => {
+            let path = codec::resource_uri(&url, &[("history", "true")]);
+            let body = request(&cli.data_dir, &target, Method::GET, &path, &[], None).await?;
+            let versions: Vec<String> = serde_json::from_slice(&body)?;
+            for version in versions {
+                println!("{}", version);
+            }
+            Ok(())
+        }
+        Command::Subscribe { url, target } => subscribe(&cli.data_dir, &target, &url).await,
+        Command::Rotate => {
+            let rotation = Keystore::open(&cli.data_dir)?.rotate()?;
+            println!("{}", serde_json::to_string(&rotation)?);
+            Ok(())
+        }
+    }
+}
+
+async fn run(
+    data_dir: PathBuf,
+    proxy: Option<SocketAddr>,
+    admin_token: Option<String>,
+    discovery: Discovery,
+    memory: bool,
+    subscriptions: Vec<String>,
+    tickets: Vec<BraidTicket>,
+) -> CliResult {
+    let mut builder = BraidIrohNode::builder()
+        .keystore(&data_dir)
+        .discovery(match discovery {
+            Discovery::Real => DiscoveryConfig::Real,
+            Discovery::Mock => DiscoveryConfig::mock(),
+        });
+    if !memory {
+        builder = builder.storage(StorageConfig::Disk(data_dir.join("store")));
+    }
+    if let Some(addr) = proxy {
+        builder = builder.proxy(addr);
+    }
+    if let Some(token) = admin_token {
+        builder = builder.admin_token(token);
+    }
+    for url in &subscriptions {
+        builder = builder.subscribe(url, vec![]);
+    }
+    let node = builder.spawn().await?;
+
+    for ticket in &tickets {
+        let _updates = node.redeem(ticket).await?;
+    }
+
+    println!("node id: {}", node.node_id());
+    println!(
+        "address: {}",
+        serde_json::to_string(&node.node_addr().await?)?
+    );
+    for url in subscriptions.iter().chain(tickets.iter().map(|t| &t.url)) {
+        println!("ticket for {}: {}", url, node.ticket(url));
+    }
+
+    tokio::signal::ctrl_c().await?;
+    node.shutdown().await?;
+    Ok(())
+}
+
+async fn addr(data_dir: PathBuf, ticket: Option<String>) -> CliResult {
+    let node = BraidIrohNode::builder()
+        .keystore(&data_dir)
+        .discovery(DiscoveryConfig::Real)
+        .spawn()
+        .await?;
+    println!("{}", serde_json::to_string(&node.node_addr().await?)?);
+    if let Some(url) = ticket {
+        println!("{}", node.ticket(&url));
+    }
+    node.shutdown().await?;
+    Ok(())
+}
+
+/// An HTTP/3 client on a fresh identity, and the peer to send to.
+async fn connect(data_dir: &Path, target: &Target) -> CliResult<(IrohH3Client, EndpointId)> {
+    let peer = match target.peer {
+        Some(peer) => peer,
+        None => Keystore::open(data_dir)?.endpoint_id(),
+    };
+    let endpoint = Endpoint::builder().bind().await?;
+    Ok((IrohH3Client::new(endpoint, BRAID_H3_ALPN.to_vec()), peer))
+}
+
+/// Send a request for `path`, an encoded path and query (see
+/// `codec::resource_uri`), and return the response body.
+async fn request(
+    data_dir: &Path,
+    target: &Target,
+    method: Method,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: Option<Bytes>,
+) -> CliResult<Bytes> {
+    let (client, peer) = connect(data_dir, target).await?;
+    let url = format!("https://{}{}", peer, path);
+    let mut builder = client.request(method, &url);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    let request = match body {
+        Some(body) => builder.bytes(body)?,
+        None => builder.build()?,
+    };
+    let response = request.send().await?;
+    if target.include {
+        print_head(response.status, &response.headers);
+    }
+    let status = response.status;
+    let body = response.bytes().await?;
+    if !status.is_success() {
+        return Err(format!("{}: {}", status, String::from_utf8_lossy(&body).trim()).into());
+    }
+    Ok(body)
+}
+
+async fn subscribe(data_dir: &Path, target: &Target, url: &str) -> CliResult {
+    let (client, peer) = connect(data_dir, target).await?;
+    let request = client
+        .request(
+            Method::GET,
+            format!("https://{}{}", peer, codec::resource_uri(url, &[])),
+        )
+        .header("subscribe", "true")
+        .build()?;
+    let response = request.send().await?;
+    if target.include {
+        print_head(response.status, &response.headers);
+    }
+    if response.status.as_u16() != 209 && !response.status.is_success() {
+        return Err(format!("{}", response.status).into());
+    }
+    let mut chunks = response.bytes_stream();
+    let mut stdout = std::io::stdout();
+    while let Some(chunk) = chunks.next().await {
+        stdout.write_all(&chunk?)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn print_head(status: StatusCode, headers: &http::HeaderMap) {
+    eprintln!("{}", status);
+    for (name, value) in headers {
+        eprintln!("{}: {}", name, value.to_str().unwrap_or("<binary>"));
+    }
+    eprintln!();
+}
@@ -0,0 +1,236 @@
+//! Step-by-step node configuration.
+//!
+//! `BraidIrohNode::builder()` starts from `BraidIrohConfig::default()`: a
+//! random identity, mock discovery, in-memory storage, no proxy, the
+//! default relays and an open ACL. Everything a node needs before it starts
+//! serving is set here, including the merge types to register, which are
+//! in place before the node serves its first request, and the resources to
+//! subscribe to, which `spawn` joins before returning.
+
+use iroh::{EndpointId, RelayMode, SecretKey};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::acl::Acl;
+use crate::discovery::DiscoveryConfig;
+use crate::error::Result;
+use crate::keystore::Keystore;
+use crate::merge::MergeType;
+use crate::node::{BraidIrohConfig, BraidIrohNode, ProxyConfig};
+use crate::redact::RedactionPolicy;
+use crate::storage::StorageConfig;
+
+/// Builds a `BraidIrohNode`.
+#[derive(Clone, Default)]
+pub struct BraidIrohNodeBuilder {
+    config: BraidIrohConfig,
+    /// Load the identity from this keystore at spawn time.
+    keystore: Option<PathBuf>,
+    /// Set on the proxy config at spawn time, whichever proxy is chosen.
+    admin_token: Option<String>,
+    subscriptions: Vec<(String, Vec<EndpointId>)>,
+}
+
+impl BraidIrohNodeBuilder {
+    /// A builder with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from an existing configuration.
+    pub fn from_config(config: BraidIrohConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Use this identity.
+    pub fn secret_key(mut self, key: SecretKey) -> Self {
+        self.config.secret_key = Some(key);
+        self.keystore = None;
+        self
+    }
+
+    /// Load the identity from the keystore in `dir`, creating it on first
+    /// run. See `Keystore`.
+    pub fn keystore(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.keystore = Some(dir.into());
+        self.config.secret_key = None;
+        self
+    }
+
+    /// How the node finds peers.
+    pub fn discovery(mut self, discovery: DiscoveryConfig) -> Self {
+        self.config.discovery = discovery;
+        self
+    }
+
+    /// Where resources and their history are stored.
+    pub fn storage(mut self, storage: StorageConfig) -> Self {
+        self.config.storage = storage;
+        self
+    }
+
+    /// Serve the HTTP/1.1 proxy on `listen_addr`, answering requests that
+    /// name no peer from this node.
+    pub fn proxy(self, listen_addr: SocketAddr) -> Self {
+        self.proxy_config(ProxyConfig {
+            listen_addr,
+            admin_token: None,
+            default_peer: None,
+            serve_local: true,
+        })
+    }
+
+    /// Serve the HTTP/1.1 proxy with full control over its routing.
+    pub fn proxy_config(mut self, proxy: ProxyConfig) -> Self {
+        self.config.proxy_config = Some(proxy);
+        self
+    }
+
+    /// Don't start the proxy (the default).
+    pub fn no_proxy(mut self) -> Self {
+        self.config.proxy_config = None;
+        self
+    }
+
+    /// Serve the admin API on the proxy listener, for requests bearing
+    /// `token`. See `admin`; has no effect without a proxy. `spawn` fails
+    /// if the token is empty or blank.
+    pub fn admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(token.into());
+        self
+    }
+
+    /// Which relay servers to use; `RelayMode::Disabled` for direct
+    /// connections only.
+    pub fn relay_mode(mut self, relay_mode: RelayMode) -> Self {
+        self.config.relay_mode = relay_mode;
+        self
+    }
+
+    /// How resource bodies appear in trace logs.
+    pub fn redaction(mut self, redaction: RedactionPolicy) -> Self {
+        self.config.redaction = redaction;
+        self
+    }
+
+    /// Access rules to start with.
+    pub fn acl(mut self, acl: Acl) -> Self {
+        self.config.acl = acl;
+        self
+    }
+
+    /// Register a merge type before the node starts serving; see
+    /// `BraidIrohConfig::merge_types`.
+    pub fn merge_type(mut self, merge_type: Arc<dyn MergeType>) -> Self {
+        self.config.merge_types.push(merge_type);
+        self
+    }
+
+    /// Subscribe to `url` once the node is up, joining through
+    /// `bootstrap`. Its updates can be read with `BraidIrohNode::updates`.
+    pub fn subscribe(mut self, url: &str, bootstrap: Vec<EndpointId>) -> Self {
+        self.subscriptions.push((url.to_string(), bootstrap));
+        self
+    }
+
+    /// The configuration built so far.
+    pub fn config(&self) -> &BraidIrohConfig {
+        &self.config
+    }
+
+    /// Start the node and join its initial subscriptions. If a subscription fails the node is shut down again.
+    pub async fn spawn(self) -> Result<BraidIrohNode> {
+        let mut config = self.config;
+        if let Some(dir) = &self.keystore {
+            config.secret_key = Some(Keystore::open(dir)?.secret_key().clone());
+        }
+        if let (Some(proxy), Some(token)) = (config.proxy_config.as_mut(), self.admin_token) {
+            proxy.admin_token = Some(token);
+        }
+
+        let node = BraidIrohNode::spawn(config).await?;
+        for (url, bootstrap) in self.subscriptions {
+            if let Err(e) = node.subscribe(&url, bootstrap).await {
+                let _ = node.shutdown().await;
+                return Err(e);
+            }
+        }
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_defaults_match_config_defaults() {
+        let builder = BraidIrohNode::builder();
+        assert!(builder.config().proxy_config.is_none());
+        assert!(builder.config().secret_key.is_none());
+        assert_eq!(builder.config().acl, Acl::open());
+    }
+
+    #[test]
+    fn test_builder_sets_options() {
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let builder = BraidIrohNode::builder()
+            .keystore("/tmp/unused")
+            .secret_key(SecretKey::from_bytes(&[7; 32]))
+            .proxy(addr)
+            .relay_mode(RelayMode::Disabled)
+            .admin_token("s3cret")
+            .subscribe("/doc", vec![]);
+
+        assert!(builder.keystore.is_none());
+        assert_eq!(
+            builder.config().secret_key.as_ref().map(|k| k.public()),
+            Some(SecretKey::from_bytes(&[7; 32]).public())
+        );
+        let proxy = builder.config().proxy_config.as_ref().unwrap();
+        assert_eq!(proxy.listen_addr, addr);
+        assert!(proxy.default_peer.is_none());
+        assert_eq!(builder.admin_token.as_deref(), Some("s3cret"));
+        assert_eq!(builder.subscriptions.len(), 1);
+        assert!(builder.no_proxy().config().proxy_config.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_merge_types_are_registered_at_spawn() {
+        use crate::dag::VersionGraph;
+        use bytes::Bytes;
+
+        struct Constant;
+        impl MergeType for Constant {
+            fn name(&self) -> &str {
+                "constant"
+            }
+            fn merge(&self, _graph: &VersionGraph) -> Result<Bytes> {
+                Ok(Bytes::from_static(b"same"))
+            }
+        }
+
+        let builder = BraidIrohNode::builder()
+            .relay_mode(RelayMode::Disabled)
+            .merge_type(Arc::new(Constant));
+        assert_eq!(builder.config().merge_types.len(), 1);
+        let node = builder.spawn().await.unwrap();
+        assert!(node.merge_types().get("constant").is_some());
+        node.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_spawn_rejects_empty_admin_token() {
+        let builder = BraidIrohNode::builder()
+            .proxy("127.0.0.1:0".parse().unwrap())
+            .admin_token(" ");
+        assert!(matches!(
+            builder.spawn().await,
+            Err(crate::error::BraidIrohError::Proxy(_))
+        ));
+    }
+}
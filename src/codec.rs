@@ -0,0 +1,500 @@
+//! Braid-HTTP wire encoding.
+//!
+//! Helpers for converting between `Update`s and the way Braid clients put
+//! them on the wire: structured `Version`/`Parents` header values, PUT
+//! requests with snapshot or patch bodies, and the multi-update framing
+//! used inside a 209 subscription response.
+
+use braid_http_rs::{Patch, Update, Version};
+use bytes::{BufMut, Bytes, BytesMut};
+use http::HeaderMap;
+
+/// Media type of the JSON envelope (a serialized `Update`), served instead
+/// of the plain resource body when a client asks for it in `Accept`.
+pub const BRAID_JSON: &str = "application/braid+json";
+
+/// A Braid request that could not be parsed.
+#[derive(Debug, thiserror::Error)]
+pub enum HeaderError {
+    /// A header value is not valid for its header.
+    #[error("malformed {header} header: {reason}")]
+    Malformed {
+        header: &'static str,
+        reason: String,
+    },
+    /// A patch inside a `Patches: N` body is malformed.
+    #[error("malformed patch {index}: {reason}")]
+    Patch { index: usize, reason: String },
+}
+
+fn malformed(header: &'static str, reason: impl Into<String>) -> HeaderError {
+    HeaderError::Malformed {
+        header,
+        reason: reason.into(),
+    }
+}
+
+/// Parse a Braid version list header value.
+///
+/// Accepts the structured form `"v1", "v2"` (with `\"` and `\\` escapes)
+/// as well as bare comma-separated tokens sent by simpler clients.
+pub fn parse_versions(header: &'static str, value: &str) -> Result<Vec<Version>, HeaderError> {
+    let mut versions = Vec::new();
+    let mut chars = value.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut item = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some(escaped) => item.push(escaped),
+                        None => return Err(malformed(header, "dangling escape")),
+                    },
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    c => item.push(c),
+                }
+            }
+            if !closed {
+                return Err(malformed(header, "unterminated quoted string"));
+            }
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+            match chars.next() {
+                None | Some(',') => {}
+                Some(c) => {
+                    return Err(malformed(
+                        header,
+                        format!("unexpected '{}' after version", c),
+                    ))
+                }
+            }
+        } else {
+            for c in chars.by_ref() {
+                if c == ',' {
+                    break;
+                }
+                if c == '"' {
+                    return Err(malformed(header, "stray quote"));
+                }
+                item.push(c);
+            }
+            item = item.trim().to_string();
+        }
+
+        if item.is_empty() {
+            return Err(malformed(header, "empty version"));
+        }
+        versions.push(Version::String(item));
+    }
+
+    Ok(versions)
+}
+
+/// Split a `Content-Range` value such as `json .a.b` or `text [3:5]` into
+/// its unit and range.
+pub fn parse_content_range(value: &str) -> Result<(String, String), HeaderError> {
+    let value = value.trim();
+    match value.split_once(char::is_whitespace) {
+        Some((unit, range)) if !unit.is_empty() && !range.trim().is_empty() => {
+            Ok((unit.to_string(), range.trim().to_string()))
+        }
+        _ => Err(malformed(
+            "Content-Range",
+            format!("expected '<unit> <range>', got '{}'", value),
+        )),
+    }
+}
+
+fn header_str<'a>(
+    headers: &'a HeaderMap,
+    name: &'static str,
+) -> Result<Option<&'a str>, HeaderError> {
+    match headers.get(name) {
+        Some(value) => value
+            .to_str()
+            .map(Some)
+            .map_err(|_| malformed(name, "not valid ASCII")),
+        None => Ok(None),
+    }
+}
+
+/// Build an `Update` from a Braid PUT request.
+///
+/// Reads `Version`, `Parents`, `Merge-Type` and `Content-Type`. The body
+/// becomes a snapshot unless the request carries patches: a single patch
+/// via a `Content-Range` header, or several via `Patches: N` with each
+/// patch framed by its own `Content-Length`/`Content-Range` headers.
+pub fn parse_put(headers: &HeaderMap, body: Bytes) -> Result<Update, HeaderError> {
+    let version = header_str(headers, "version")?
+        .map(|v| parse_versions("Version", v))
+        .transpose()?
+        .unwrap_or_default();
+    let parents = header_str(headers, "parents")?
+        .map(|v| parse_versions("Parents", v))
+        .transpose()?
+        .unwrap_or_default();
+
+    let patches = if let Some(count) = header_str(headers, "patches")? {
+        let count: usize = count
+            .trim()
+            .parse()
+            .map_err(|_| malformed("Patches", format!("'{}' is not a count", count)))?;
+        Some(parse_patches(&body, count)?)
+    } else if let Some(range) = header_str(headers, "content-range")? {
+        let (unit, range) = parse_content_range(range)?;
+        Some(vec![Patch::new(unit, range, body.clone())])
+    } else {
+        None
+    };
+
+    let mut update = Update::snapshot(Version::String(String::new()), body);
+    update.version = version;
+    update.parents = parents;
+    update.merge_type = header_str(headers, "merge-type")?.map(|v| v.trim().to_string());
+    update.content_type = header_str(headers, "content-type")?.map(|v| v.trim().to_string());
+    if patches.is_some() {
+        update.body = None;
+        update.patches = patches;
+    }
+    Ok(update)
+}
+
+/// Parse `count` patches out of a multi-patch body. `count` and each
+/// patch's Content-Length come from the peer, so neither is trusted to size
+/// allocations or index the body.
+fn parse_patches(body: &[u8], count: usize) -> Result<Vec<Patch>, HeaderError> {
+    let mut patches = Vec::new();
+    let mut pos = 0;
+
+    for index in 0..count {
+        let err = |reason: String| HeaderError::Patch { index, reason };
+
+        // Skip blank lines between patches.
+        while body[pos..].starts_with(b"\r\n") || body[pos..].starts_with(b"\n") {
+            pos += if body[pos] == b'\r' { 2 } else { 1 };
+        }
+
+        let mut length = None;
+        let mut range = None;
+        loop {
+            let rest = &body[pos..];
+            let Some(eol) = rest.iter().position(|&b| b == b'\n') else {
+                return Err(err("headers not terminated".into()));
+            };
+            let line = std::str::from_utf8(&rest[..eol])
+                .map_err(|_| err("header is not UTF-8".into()))?
+                .trim_end_matches('\r');
+            pos += eol + 1;
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| err(format!("bad header line '{}'", line)))?;
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => {
+                    length = Some(
+                        value
+                            .trim()
+                            .parse::<usize>()
+                            .map_err(|_| err(format!("bad Content-Length '{}'", value.trim())))?,
+                    )
+                }
+                "content-range" => range = Some(parse_content_range(value)?),
+                _ => {}
+            }
+        }
+
+        let length = length.ok_or_else(|| err("missing Content-Length".into()))?;
+        let (unit, range) = range.ok_or_else(|| err("missing Content-Range".into()))?;
+        let end = pos
+            .checked_add(length)
+            .filter(|&end| end <= body.len())
+            .ok_or_else(|| err("body shorter than Content-Length".into()))?;
+        let content = Bytes::copy_from_slice(&body[pos..end]);
+        pos = end;
+        patches.push(Patch::new(unit, range, content));
+    }
+
+    Ok(patches)
+}
+
+/// Render a version list as a Braid header value: `"v1", "v2"`.
+pub fn format_versions(versions: &[Version]) -> String {
+    versions
+        .iter()
+        .map(|v| {
+            format!(
+                "\"{}\"",
+                v.to_string().replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Encode one update as a frame of a Braid subscription body.
+///
+/// Each frame carries its own headers followed by the snapshot body, or by
+/// a `Patches: N` block when the update is a set of patches. Frames are
+/// separated by a blank line.
+pub fn encode_update(update: &Update) -> Bytes {
+    let mut out = BytesMut::new();
+
+    if !update.version.is_empty() {
+        put_header(&mut out, "Version", &format_versions(&update.version));
+    }
+    if !update.parents.is_empty() {
+        put_header(&mut out, "Parents", &format_versions(&update.parents));
+    }
+    if let Some(merge_type) = &update.merge_type {
+        put_header(&mut out, "Merge-Type", merge_type);
+    }
+    if let Some(content_type) = &update.content_type {
+        put_header(&mut out, "Content-Type", content_type);
+    }
+
+    match &update.patches {
+        Some(patches) if !patches.is_empty() => {
+            put_header(&mut out, "Patches", &patches.len().to_string());
+            out.put_slice(b"\r\n");
+            out.put_slice(&encode_patches(patches));
+        }
+        _ => {
+            let body = update.body.clone().unwrap_or_default();
+            put_header(&mut out, "Content-Length", &body.len().to_string());
+            out.put_slice(b"\r\n");
+            out.put_slice(&body);
+            out.put_slice(b"\r\n\r\n");
+        }
+    }
+
+    out.freeze()
+}
+
+/// Encode patches as the body of a `Patches: N` message, each framed by
+/// its own `Content-Length` and `Content-Range` headers.
+pub fn encode_patches(patches: &[Patch]) -> Bytes {
+    let mut out = BytesMut::new();
+    for patch in patches {
+        put_header(&mut out, "Content-Length", &patch.content.len().to_string());
+        put_header(
+            &mut out,
+            "Content-Range",
+            &format!("{} {}", patch.unit, patch.range),
+        );
+        out.put_slice(b"\r\n");
+        out.put_slice(&patch.content);
+        out.put_slice(b"\r\n\r\n");
+    }
+    out.freeze()
+}
+
+/// Whether the client asked for the JSON envelope instead of the resource
+/// body (`Accept: application/braid+json`).
+pub fn wants_braid_json(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(http::header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.split(';').next().unwrap_or("").trim() == BRAID_JSON)
+}
+
+/// Path and query of a request for resource `url`: the URL percent-encoded
+/// as a path (keeping its `/`s, with a leading one added if missing) and
+/// each query parameter value encoded, so `?`, `#`, `&` or spaces in either
+/// survive the trip.
+pub fn resource_uri(url: &str, query: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(url.len() + 1);
+    if !url.starts_with('/') {
+        out.push('/');
+    }
+    percent_encode_into(&mut out, url, b"/");
+    for (i, (name, value)) in query.iter().enumerate() {
+        out.push(if i == 0 { '?' } else { '&' });
+        percent_encode_into(&mut out, name, b"");
+        out.push('=');
+        percent_encode_into(&mut out, value, b"");
+    }
+    out
+}
+
+/// Append `value` to `out`, percent-encoding every byte except unreserved
+/// characters and those in `keep`.
+fn percent_encode_into(out: &mut String, value: &str, keep: &[u8]) {
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ if keep.contains(&b) => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+}
+
+fn put_header(out: &mut BytesMut, name: &str, value: &str) {
+    out.put_slice(name.as_bytes());
+    out.put_slice(b": ");
+    out.put_slice(value.as_bytes());
+    out.put_slice(b"\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_parse_versions() {
+        let v = |s: &str| Version::String(s.into());
+        assert_eq!(
+            parse_versions("Version", r#""a", "b\"c""#).unwrap(),
+            vec![v("a"), v("b\"c")]
+        );
+        assert_eq!(
+            parse_versions("Version", "x, y").unwrap(),
+            vec![v("x"), v("y")]
+        );
+        assert!(parse_versions("Version", "").unwrap().is_empty());
+        assert!(parse_versions("Version", r#""open"#).is_err());
+        assert!(parse_versions("Version", r#""a" b"#).is_err());
+        assert!(parse_versions("Parents", "a,,b").is_err());
+    }
+
+    #[test]
+    fn test_wants_braid_json() {
+        assert!(wants_braid_json(&headers(&[(
+            "accept",
+            "text/plain, application/braid+json;q=0.9"
+        )])));
+        assert!(!wants_braid_json(&headers(&[("accept", "application/json")])));
+        assert!(!wants_braid_json(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(
+            parse_content_range("text [1:3]").unwrap(),
+            ("text".to_string(), "[1:3]".to_string())
+        );
+        assert!(parse_content_range("json").is_err());
+    }
+
+    #[test]
+    fn test_parse_put_snapshot() {
+        let h = headers(&[
+            ("version", r#""v2""#),
+            ("parents", r#""v1""#),
+            ("merge-type", "text"),
+            ("content-type", "text/plain"),
+        ]);
+        let update = parse_put(&h, Bytes::from_static(&[0xff, 0x00])).unwrap();
+        assert_eq!(update.version, vec![Version::String("v2".into())]);
+        assert_eq!(update.parents, vec![Version::String("v1".into())]);
+        assert_eq!(update.merge_type.as_deref(), Some("text"));
+        assert_eq!(update.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(update.body.as_deref(), Some(&[0xff, 0x00][..]));
+    }
+
+    #[test]
+    fn test_parse_put_single_patch() {
+        let h = headers(&[("version", "v2"), ("content-range", "text [0:0]")]);
+        let update = parse_put(&h, Bytes::from_static(b"hi")).unwrap();
+        let patches = update.patches.unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].unit, "text");
+        assert_eq!(patches[0].range, "[0:0]");
+        assert!(update.body.is_none());
+    }
+
+    #[test]
+    fn test_parse_put_multi_patch() {
+        let h = headers(&[("version", "v3"), ("patches", "2")]);
+        let body = Bytes::from_static(
+            b"Content-Length: 1\r\nContent-Range: text [0:0]\r\n\r\na\r\n\r\n\
+              Content-Length: 2\r\nContent-Range: text [5:6]\r\n\r\nbc",
+        );
+        let patches = parse_put(&h, body).unwrap().patches.unwrap();
+        assert_eq!(patches.len(), 2);
+        assert_eq!(&patches[1].content[..], b"bc");
+        assert_eq!(patches[1].range, "[5:6]");
+    }
+
+    #[test]
+    fn test_parse_put_rejects_malformed() {
+        assert!(parse_put(&headers(&[("patches", "two")]), Bytes::new()).is_err());
+        let truncated =
+            Bytes::from_static(b"Content-Length: 9\r\nContent-Range: text [0:0]\r\n\r\nab");
+        assert!(parse_put(&headers(&[("patches", "1")]), truncated).is_err());
+    }
+
+    #[test]
+    fn test_parse_put_rejects_hostile_sizes() {
+        let one_patch =
+            Bytes::from_static(b"Content-Length: 1\r\nContent-Range: text [0:0]\r\n\r\na");
+        let huge_count = headers(&[("patches", "18446744073709551615")]);
+        assert!(matches!(
+            parse_put(&huge_count, one_patch),
+            Err(HeaderError::Patch { index: 1, .. })
+        ));
+
+        let overflowing = Bytes::from_static(
+            b"Content-Length: 18446744073709551615\r\nContent-Range: text [0:0]\r\n\r\nab",
+        );
+        assert!(matches!(
+            parse_put(&headers(&[("patches", "1")]), overflowing),
+            Err(HeaderError::Patch { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_resource_uri() {
+        assert_eq!(resource_uri("/docs/a", &[]), "/docs/a");
+        assert_eq!(resource_uri("notes", &[("history", "true")]), "/notes?history=true");
+        assert_eq!(
+            resource_uri("/a b?c#d", &[("version", "v1&x=ü"), ("k", "")]),
+            "/a%20b%3Fc%23d?version=v1%26x%3D%C3%BC&k="
+        );
+    }
+
+    #[test]
+    fn test_format_versions() {
+        let versions = vec![Version::String("a".into()), Version::String("b\"c".into())];
+        assert_eq!(format_versions(&versions), r#""a", "b\"c""#);
+        assert_eq!(format_versions(&[]), "");
+    }
+
+    #[test]
+    fn test_encode_snapshot_frame() {
+        let update = Update::snapshot(Version::String("v1".into()), Bytes::from_static(b"hello"));
+        let frame = encode_update(&update);
+        assert_eq!(
+            frame,
+            Bytes::from_static(b"Version: \"v1\"\r\nContent-Length: 5\r\n\r\nhello\r\n\r\n")
+        );
+    }
+}
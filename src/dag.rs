@@ -0,0 +1,341 @@
+//! Per-resource version graph.
+//!
+//! Braid versions form a DAG: every update names the versions it was
+//! based on in its `Parents`. Two peers writing concurrently produce two
+//! updates with the same parents, and the resource then has two current
+//! heads (the *frontier*) until someone writes an update naming both.
+//! `VersionGraph` keeps that structure so history and "latest" are
+//! consistent on every peer regardless of arrival order.
+
+use braid_http_rs::Update;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::signing::Attribution;
+use crate::storage::version_string;
+
+/// One version in the graph.
+#[derive(Debug, Clone)]
+pub struct VersionNode {
+    /// The update that introduced this version.
+    pub update: Update,
+    /// Version IDs this version was based on.
+    pub parents: Vec<String>,
+    /// The verified author of this version, if it was signed.
+    pub attribution: Option<Attribution>,
+}
+
+/// A resource's versions and the parent edges between them.
+#[derive(Debug, Clone, Default)]
+pub struct VersionGraph {
+    /// Version key → node.
+    nodes: HashMap<String, VersionNode>,
+    /// Individual version ID → version key, for updates carrying several IDs.
+    ids: HashMap<String, String>,
+    /// Version keys in arrival order.
+    arrival: Vec<String>,
+    /// Every version ID that some known update names as a parent.
+    referenced: HashSet<String>,
+    /// Versions nothing builds on yet.
+    frontier: BTreeSet<String>,
+}
+
+impl VersionGraph {
+    /// Create an empty graph.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Number of versions in the graph.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the graph has no versions.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Add an update. Returns `false` if its version is already present.
+    ///
+    /// Parents do not need to be present yet; an update that arrives before
+    /// its parents is kept and the graph heals once they show up.
+    pub fn insert(&mut self, update: Update) -> bool {
+        self.insert_attributed(update, None)
+    }
+
+    /// Add an update together with its verified author. Returns `false` if
+    /// its version is already present.
+    pub fn insert_attributed(&mut self, update: Update, attribution: Option<Attribution>) -> bool {
+        let key = version_string(&update);
+        if self.nodes.contains_key(&key) {
+            return false;
+        }
+
+        let ids: Vec<String> = update.version.iter().map(|v| v.to_string()).collect();
+        let parents: Vec<String> = update.parents.iter().map(|v| v.to_string()).collect();
+
+        for parent in &parents {
+            self.referenced.insert(parent.clone());
+            if let Some(parent_key) = self.ids.get(parent) {
+                self.frontier.remove(parent_key);
+            }
+        }
+
+        if !ids.iter().any(|id| self.referenced.contains(id)) {
+            self.frontier.insert(key.clone());
+        }
+        for id in ids {
+            self.ids.insert(id, key.clone());
+        }
+        self.ids.insert(key.clone(), key.clone());
+        self.arrival.push(key.clone());
+        self.nodes.insert(
+            key,
+            VersionNode {
+                update,
+                parents,
+                attribution,
+            },
+        );
+        true
+    }
+
+    /// Look up a version by its key or any of its individual IDs.
+    pub fn get(&self, version_id: &str) -> Option<&VersionNode> {
+        self.ids.get(version_id).and_then(|key| self.nodes.get(key))
+    }
+
+    /// Whether the graph holds the given version.
+    pub fn contains(&self, version_id: &str) -> bool {
+        self.ids.contains_key(version_id)
+    }
+
+    /// The current heads, sorted.
+    pub fn frontier(&self) -> Vec<String> {
+        self.frontier.iter().cloned().collect()
+    }
+
+    /// Parent IDs that are referenced but not present in the graph.
+    pub fn missing(&self) -> Vec<String> {
+        let mut missing: Vec<String> = self
+            .referenced
+            .iter()
+            .filter(|id| !self.ids.contains_key(*id))
+            .cloned()
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    /// All versions reachable from `version_id` through parent edges,
+    /// including itself.
+    pub fn ancestors(&self, version_id: &str) -> HashSet<String> {
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<String> = self.ids.get(version_id).cloned().into_iter().collect();
+        while let Some(key) = queue.pop_front() {
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(&key) {
+                for parent in &node.parents {
+                    if let Some(parent_key) = self.ids.get(parent) {
+                        queue.push_back(parent_key.clone());
+                    }
+                }
+            }
+        }
+        seen
+    }
+
+    /// Whether `ancestor` is `descendant` or one of its ancestors.
+    pub fn is_ancestor(&self, ancestor: &str, descendant: &str) -> bool {
+        match self.ids.get(ancestor) {
+            Some(key) => self.ancestors(descendant).contains(key),
+            None => false,
+        }
+    }
+
+    /// Version keys ordered so every version comes after its parents.
+    ///
+    /// Concurrent versions are ordered by version key, so every peer holding
+    /// the same graph gets the same sequence regardless of arrival order.
+    pub fn topological_order(&self) -> Vec<String> {
+        let mut pending: HashMap<&str, usize> = HashMap::new();
+        let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (key, node) in &self.nodes {
+            let mut count = 0;
+            for parent in &node.parents {
+                if let Some(parent_key) = self.ids.get(parent) {
+                    if parent_key != key {
+                        count += 1;
+                        children
+                            .entry(parent_key.as_str())
+                            .or_default()
+                            .push(key.as_str());
+                    }
+                }
+            }
+            pending.insert(key.as_str(), count);
+        }
+
+        let mut ready: BTreeSet<&str> = pending
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(key, _)| *key)
+            .collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(key) = ready.pop_first() {
+            order.push(key.to_string());
+            for child in children.get(key).into_iter().flatten() {
+                let count = pending.get_mut(child).expect("child is a known node");
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert(*child);
+                }
+            }
+        }
+
+        // Cycles cannot come from honest peers; keep whatever is left in
+        // arrival order rather than dropping it.
+        if order.len() < self.nodes.len() {
+            let placed: HashSet<String> = order.iter().cloned().collect();
+            order.extend(
+                self.arrival
+                    .iter()
+                    .filter(|k| !placed.contains(*k))
+                    .cloned(),
+            );
+        }
+        order
+    }
+
+    /// Updates in topological order (see `topological_order`).
+    pub fn history(&self) -> Vec<Update> {
+        self.topological_order()
+            .iter()
+            .filter_map(|key| self.nodes.get(key))
+            .map(|node| node.update.clone())
+            .collect()
+    }
+
+    /// The update at the head of the graph.
+    ///
+    /// With several concurrent heads the greatest version (see
+    /// `compare_versions`) wins, so all peers agree without coordination.
+    pub fn latest(&self) -> Option<&Update> {
+        self.frontier
+            .iter()
+            .max_by(|a, b| compare_versions(a, b))
+            .and_then(|key| self.nodes.get(key))
+            .map(|node| &node.update)
+    }
+}
+
+/// Total order on version IDs used to break ties between concurrent
+/// versions.
+///
+/// Numeric IDs compare numerically and `<agent>-<seq>` IDs compare by
+/// sequence number first, then agent; anything else compares as a string.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn seq(v: &str) -> Option<(u64, &str)> {
+        if let Ok(n) = v.parse() {
+            return Some((n, ""));
+        }
+        let (agent, n) = v.rsplit_once('-')?;
+        Some((n.parse().ok()?, agent))
+    }
+
+    match (seq(a), seq(b)) {
+        (Some((na, aa)), Some((nb, ab))) => na.cmp(&nb).then(aa.cmp(ab)).then(a.cmp(b)),
+        _ => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use braid_http_rs::Version;
+    use bytes::Bytes;
+
+    fn update(version: &str, parents: &[&str]) -> Update {
+        let mut update = Update::snapshot(
+            Version::String(version.into()),
+            Bytes::from(version.to_string()),
+        );
+        update.parents = parents
+            .iter()
+            .map(|p| Version::String(p.to_string()))
+            .collect();
+        update
+    }
+
+    #[test]
+    fn test_linear_history() {
+        let mut graph = VersionGraph::new();
+        assert!(graph.insert(update("a", &[])));
+        assert!(graph.insert(update("b", &["a"])));
+        assert!(!graph.insert(update("b", &["a"])));
+
+        assert_eq!(graph.frontier(), vec!["b"]);
+        assert_eq!(graph.topological_order(), vec!["a", "b"]);
+        assert!(graph.is_ancestor("a", "b"));
+        assert!(!graph.is_ancestor("b", "a"));
+    }
+
+    #[test]
+    fn test_concurrent_heads_and_merge() {
+        let mut graph = VersionGraph::new();
+        graph.insert(update("a", &[]));
+        graph.insert(update("c", &["a"]));
+        graph.insert(update("b", &["a"]));
+
+        assert_eq!(graph.frontier(), vec!["b", "c"]);
+        assert_eq!(graph.topological_order(), vec!["a", "b", "c"]);
+        assert_eq!(version_string(graph.latest().unwrap()), "c");
+
+        graph.insert(update("d", &["b", "c"]));
+        assert_eq!(graph.frontier(), vec!["d"]);
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("9", "10"), Ordering::Less);
+        assert_eq!(compare_versions("bob-2", "alice-10"), Ordering::Less);
+        assert_eq!(compare_versions("alice-3", "bob-3"), Ordering::Less);
+        assert_eq!(compare_versions("b", "a"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_out_of_order_arrival() {
+        let mut graph = VersionGraph::new();
+        graph.insert(update("b", &["a"]));
+        assert_eq!(graph.missing(), vec!["a"]);
+
+        graph.insert(update("a", &[]));
+        assert!(graph.missing().is_empty());
+        assert_eq!(graph.frontier(), vec!["b"]);
+        assert_eq!(graph.topological_order(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_order_independent_of_arrival() {
+        let updates = [
+            update("a", &[]),
+            update("x", &["a"]),
+            update("y", &["a"]),
+            update("z", &["x", "y"]),
+        ];
+        let mut forward = VersionGraph::new();
+        let mut backward = VersionGraph::new();
+        for u in updates.iter() {
+            forward.insert(u.clone());
+        }
+        for u in updates.iter().rev() {
+            backward.insert(u.clone());
+        }
+
+        assert_eq!(forward.topological_order(), backward.topological_order());
+        assert_eq!(forward.frontier(), backward.frontier());
+    }
+}
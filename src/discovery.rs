@@ -61,6 +61,11 @@ impl MockDiscoveryMap {
     pub fn add_node(&self, id: EndpointId, data: EndpointData) {
         self.peers.write().unwrap().insert(id, Arc::new(data));
     }
+
+    /// IDs of every node registered in the map.
+    pub fn peers(&self) -> Vec<EndpointId> {
+        self.peers.read().unwrap().keys().copied().collect()
+    }
 }
 
 impl IntoAddressLookup for MockDiscoveryMap {
@@ -149,7 +154,7 @@ mod tests {
         let map = MockDiscoveryMap::new();
 
         for i in 0..5 {
-            let id = EndpointId::from_bytes(&[i as u8; 32]).expect("valid test key");
+            let id = iroh::SecretKey::from_bytes(&[i as u8; 32]).public();
             let data = EndpointData::default();
             map.add_node(id, data);
         }
@@ -0,0 +1,175 @@
+//! Error type for braid_iroh.
+//!
+//! Every public API returns `braid_iroh::Result`, so callers can tell a
+//! missing topic from a closed gossip channel from a malformed request.
+//! Inside the Axum handlers the same errors turn into HTTP responses via
+//! `IntoResponse`, using `BraidIrohError::status`.
+
+use axum::response::{IntoResponse, Response};
+use http::StatusCode;
+
+use crate::codec::HeaderError;
+
+/// Result alias used throughout the crate.
+pub type Result<T, E = BraidIrohError> = std::result::Result<T, E>;
+
+/// Everything that can go wrong in a Braid-Iroh node.
+#[derive(Debug, thiserror::Error)]
+pub enum BraidIrohError {
+    /// Reading or writing the resource store failed.
+    #[error("storage error: {0}")]
+    Storage(#[from] std::io::Error),
+
+    /// An update could not be serialized or deserialized.
+    #[error("malformed update: {0}")]
+    Codec(#[from] serde_json::Error),
+
+    /// A Braid request carried malformed headers or a malformed patch body.
+    #[error(transparent)]
+    Header(#[from] HeaderError),
+
+    /// An update's signature did not verify against its claimed author.
+    #[error("invalid signature from {0}")]
+    BadSignature(String),
+
+    /// A private topic payload could not be sealed or opened, or a
+    /// resource secret is malformed.
+    #[error("private topic error: {0}")]
+    Sealed(String),
+
+    /// A capability ticket could not be parsed or doesn't match its
+    /// resource.
+    #[error("invalid ticket: {0}")]
+    Ticket(String),
+
+    /// A peer ID in a request could not be parsed.
+    #[error("invalid peer id: {0}")]
+    InvalidPeer(String),
+
+    /// A well-formed request or argument the node can't act on, such as
+    /// a PUT to a directory or a node name that isn't a plain file name.
+    #[error("bad request: {0}")]
+    BadRequest(String),
+
+    /// The peer lacks the right needed for the operation.
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
+    /// The requested resource does not exist.
+    #[error("resource not found: {0}")]
+    NotFound(String),
+
+    /// The requested version of a resource does not exist.
+    #[error("version {version} not found for {url}")]
+    VersionNotFound { url: String, version: String },
+
+    /// No gossip topic is joined for this resource URL.
+    #[error("topic not found: {0}")]
+    TopicNotFound(String),
+
+    /// The gossip layer rejected an operation or its channel has closed.
+    #[error("gossip error: {0}")]
+    Gossip(String),
+
+    /// Concurrent versions could not be merged.
+    #[error("merge failed: {0}")]
+    Merge(String),
+
+    /// The iroh endpoint could not be bound or used.
+    #[error("endpoint error: {0}")]
+    Endpoint(String),
+
+    /// A request to a remote peer over HTTP/3 failed.
+    #[error("peer request failed: {0}")]
+    Peer(String),
+
+    /// The TCP proxy bridge could not start or forward a request.
+    #[error("proxy error: {0}")]
+    Proxy(String),
+
+    /// The node has been shut down and no longer accepts work.
+    #[error("node is shutting down")]
+    ShuttingDown,
+}
+
+impl BraidIrohError {
+    /// The HTTP status this error maps to when returned from a handler.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            BraidIrohError::Header(_) | BraidIrohError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            BraidIrohError::BadSignature(_) | BraidIrohError::Sealed(_) => StatusCode::BAD_REQUEST,
+            BraidIrohError::Codec(_) | BraidIrohError::Ticket(_) => StatusCode::BAD_REQUEST,
+            BraidIrohError::InvalidPeer(_) => StatusCode::BAD_REQUEST,
+            BraidIrohError::Forbidden(_) => StatusCode::FORBIDDEN,
+            BraidIrohError::NotFound(_)
+            | BraidIrohError::VersionNotFound { .. }
+            | BraidIrohError::TopicNotFound(_) => StatusCode::NOT_FOUND,
+            BraidIrohError::Merge(_) => StatusCode::CONFLICT,
+            BraidIrohError::Peer(_) | BraidIrohError::Proxy(_) => StatusCode::BAD_GATEWAY,
+            BraidIrohError::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            BraidIrohError::Storage(_)
+            | BraidIrohError::Gossip(_)
+            | BraidIrohError::Endpoint(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub(crate) fn gossip(e: impl std::fmt::Display) -> Self {
+        BraidIrohError::Gossip(e.to_string())
+    }
+
+    pub(crate) fn peer(e: impl std::fmt::Display) -> Self {
+        BraidIrohError::Peer(e.to_string())
+    }
+
+    pub(crate) fn proxy(e: impl std::fmt::Display) -> Self {
+        BraidIrohError::Proxy(e.to_string())
+    }
+}
+
+impl IntoResponse for BraidIrohError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            tracing::error!("request failed: {}", self);
+        }
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_mapping() {
+        assert_eq!(
+            BraidIrohError::TopicNotFound("/doc".into()).status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            BraidIrohError::BadRequest("x".into()).status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            BraidIrohError::Proxy("down".into()).status(),
+            StatusCode::BAD_GATEWAY
+        );
+        assert_eq!(
+            BraidIrohError::ShuttingDown.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            BraidIrohError::gossip("closed").status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn test_header_error_is_bad_request() {
+        let err: BraidIrohError = crate::codec::parse_versions("Version", "\"open")
+            .unwrap_err()
+            .into();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert!(err.to_string().contains("Version"));
+    }
+}
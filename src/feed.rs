@@ -0,0 +1,122 @@
+//! Per-resource update feeds.
+//!
+//! Every update a node accepts — from a local `put`, an HTTP/3 PUT, or
+//! gossip — is published on the feed for its resource URL. Consumers get a
+//! typed `Stream<Item = Update>` instead of raw gossip events.
+
+use braid_http_rs::Update;
+use futures::{Stream, StreamExt};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+
+/// How many updates a slow consumer may fall behind before its stream is
+/// cut off.
+const FEED_CAPACITY: usize = 256;
+
+/// A stream of updates for a single resource.
+pub type UpdateStream = Pin<Box<dyn Stream<Item = Update> + Send>>;
+
+/// Fan-out of accepted updates, keyed by normalized resource URL.
+#[derive(Clone, Default)]
+pub struct UpdateFeed {
+    channels: Arc<Mutex<HashMap<String, broadcast::Sender<Update>>>>,
+}
+
+impl UpdateFeed {
+    /// Create an empty feed.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Stream every update published for `url` from now on. A consumer
+    /// that falls more than `FEED_CAPACITY` updates behind has missed some,
+    /// so its stream ends there rather than silently skipping ahead; resolve
+    /// the current state and subscribe again to continue.
+    pub fn subscribe(&self, url: &str) -> UpdateStream {
+        let receiver = self
+            .channels
+            .lock()
+            .entry(url.to_string())
+            .or_insert_with(|| broadcast::channel(FEED_CAPACITY).0)
+            .subscribe();
+
+        let url = url.to_string();
+        BroadcastStream::new(receiver)
+            .take_while(move |item| {
+                if let Err(BroadcastStreamRecvError::Lagged(n)) = item {
+                    tracing::warn!(url = %url, skipped = n, "update stream lagged, ending it");
+                }
+                futures::future::ready(item.is_ok())
+            })
+            .filter_map(|item| futures::future::ready(item.ok()))
+            .boxed()
+    }
+
+    /// Publish an update to everyone streaming `url`.
+    pub fn publish(&self, url: &str, update: &Update) {
+        let mut channels = self.channels.lock();
+        if let Some(sender) = channels.get(url) {
+            if sender.send(update.clone()).is_err() {
+                // Nobody is listening anymore.
+                channels.remove(url);
+            }
+        }
+    }
+
+    /// Number of live streams for `url`.
+    pub fn subscriber_count(&self, url: &str) -> usize {
+        self.channels
+            .lock()
+            .get(url)
+            .map(|s| s.receiver_count())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use braid_http_rs::Version;
+    use bytes::Bytes;
+
+    fn update(version: &str) -> Update {
+        Update::snapshot(Version::String(version.into()), Bytes::from_static(b"x"))
+    }
+
+    #[tokio::test]
+    async fn test_feed_delivers_to_subscribers() {
+        let feed = UpdateFeed::new();
+        let mut stream = feed.subscribe("/doc");
+        feed.publish("/doc", &update("v1"));
+        feed.publish("/other", &update("v2"));
+
+        let received = stream.next().await.unwrap();
+        assert_eq!(received.version, vec![Version::String("v1".into())]);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_stream_ends() {
+        let feed = UpdateFeed::new();
+        let mut stream = feed.subscribe("/doc");
+        for i in 0..=FEED_CAPACITY {
+            feed.publish("/doc", &update(&format!("v{}", i)));
+        }
+        assert!(stream.next().await.is_none());
+    }
+
+    #[test]
+    fn test_feed_prunes_dropped_subscribers() {
+        let feed = UpdateFeed::new();
+        let stream = feed.subscribe("/doc");
+        assert_eq!(feed.subscriber_count("/doc"), 1);
+
+        drop(stream);
+        feed.publish("/doc", &update("v1"));
+        assert_eq!(feed.subscriber_count("/doc"), 0);
+        assert!(feed.channels.lock().is_empty());
+    }
+}
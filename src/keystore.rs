@@ -0,0 +1,331 @@
+//! Persistent node identity.
+//!
+//! A `Keystore` keeps the node's iroh `SecretKey` in a data directory, so a
+//! node keeps its `EndpointId` across restarts. The key is generated at
+//! random on first use and written (as hex) to `secret.key`, readable by
+//! the owner only on Unix.
+//!
+//! `rotate` replaces the key with a fresh one and records a `KeyRotation`
+//! in `rotations.jsonl`: a statement naming the old and new `EndpointId`s,
+//! signed by both keys. Peers that knew the old identity can verify the
+//! statement (or a chain of them) to learn the new one.
+
+use iroh::{EndpointId, SecretKey};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{BraidIrohError, Result};
+use crate::signing::{hex_decode, hex_encode, Attribution};
+
+/// File holding the current secret key.
+pub const KEY_FILE: &str = "secret.key";
+
+/// File holding every rotation statement, one JSON object per line.
+pub const ROTATIONS_FILE: &str = "rotations.jsonl";
+
+/// File a rotation writes the new key to before it replaces `KEY_FILE`.
+const STAGED_KEY_FILE: &str = "secret.key.new";
+
+/// Domain separator for rotation statements.
+const ROTATION_CONTEXT: &[u8] = b"braid-iroh/key-rotation/v1\0";
+
+/// A node's secret key, stored on disk.
+pub struct Keystore {
+    dir: PathBuf,
+    key: SecretKey,
+}
+
+impl Keystore {
+    /// Open the keystore in `dir`, creating the directory and a new random
+    /// key if there is none yet. A rotation interrupted by a crash is
+    /// finished if its statement was recorded, and undone otherwise.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+
+        let path = dir.join(KEY_FILE);
+        let staged = dir.join(STAGED_KEY_FILE);
+        if staged.exists() {
+            let staged_id = read_key(&staged).ok().map(|key| key.public());
+            let recorded = read_rotations(&dir)?.last().map(|r| r.new.author);
+            if staged_id.is_some() && staged_id == recorded {
+                std::fs::rename(&staged, &path)?;
+                tracing::info!(id = ?staged_id, "finished interrupted key rotation");
+            } else {
+                std::fs::remove_file(&staged)?;
+                tracing::warn!(dir = %dir.display(), "discarded unrecorded staged key");
+            }
+        }
+        let key = if path.exists() {
+            read_key(&path)?
+        } else {
+            let key = generate_key();
+            write_key(&path, &key)?;
+            tracing::info!(id = %key.public(), dir = %dir.display(), "generated node key");
+            key
+        };
+        Ok(Self { dir, key })
+    }
+
+    /// Directory this keystore lives in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The current secret key.
+    pub fn secret_key(&self) -> &SecretKey {
+        &self.key
+    }
+
+    /// The identity of the current key.
+    pub fn endpoint_id(&self) -> EndpointId {
+        self.key.public()
+    }
+
+    /// Replace the key with a fresh one, recording and returning the
+    /// handover statement. The old key is discarded. A node already running
+    /// with the old key keeps it until it is restarted.
+    pub fn rotate(&mut self) -> Result<KeyRotation> {
+        let new_key = generate_key();
+        let rotation = KeyRotation::sign(&self.key, &new_key, unix_now());
+
+        // Write the new key aside first, so the statement is only recorded
+        // for a key that made it to disk.
+        let path = self.dir.join(KEY_FILE);
+        let staged = self.dir.join(STAGED_KEY_FILE);
+        write_key(&staged, &new_key)?;
+
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(ROTATIONS_FILE))?;
+        log.write_all(&serde_json::to_vec(&rotation)?)?;
+        log.write_all(b"\n")?;
+        log.sync_all()?;
+
+        std::fs::rename(&staged, &path)?;
+        tracing::info!(old = %rotation.old.author, new = %rotation.new.author, "rotated node key");
+        self.key = new_key;
+        Ok(rotation)
+    }
+
+    /// Every rotation recorded in this keystore, oldest first.
+    pub fn rotations(&self) -> Result<Vec<KeyRotation>> {
+        read_rotations(&self.dir)
+    }
+}
+
+fn read_rotations(dir: &Path) -> Result<Vec<KeyRotation>> {
+    let path = dir.join(ROTATIONS_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut rotations = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            rotations.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(rotations)
+}
+
+/// A signed statement that `old.author` handed over to `new.author`.
+/// Both keys sign the same message, so the statement proves the holder of
+/// the old key chose the new one and the new key accepted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRotation {
+    /// The retiring identity and its signature.
+    pub old: Attribution,
+    /// The replacement identity and its signature.
+    pub new: Attribution,
+    /// When the rotation happened, in seconds since the Unix epoch.
+    pub issued_at: u64,
+}
+
+impl KeyRotation {
+    /// Sign a handover from `old` to `new`.
+    pub fn sign(old: &SecretKey, new: &SecretKey, issued_at: u64) -> Self {
+        let message = rotation_bytes(&old.public(), &new.public(), issued_at);
+        Self {
+            old: Attribution {
+                author: old.public(),
+                signature: old.sign(&message),
+            },
+            new: Attribution {
+                author: new.public(),
+                signature: new.sign(&message),
+            },
+            issued_at,
+        }
+    }
+
+    /// Check both signatures.
+    pub fn verify(&self) -> Result<()> {
+        let message = rotation_bytes(&self.old.author, &self.new.author, self.issued_at);
+        for attribution in [&self.old, &self.new] {
+            attribution
+                .author
+                .verify(&message, &attribution.signature)
+                .map_err(|_| BraidIrohError::BadSignature(attribution.author.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Follow a chain of rotations from `from`, verifying each one, and return
+/// the identity it ends at. Statements that don't continue the chain are
+/// ignored.
+pub fn follow_rotations(from: EndpointId, rotations: &[KeyRotation]) -> Result<EndpointId> {
+    let mut current = from;
+    for rotation in rotations {
+        if rotation.old.author == current {
+            rotation.verify()?;
+            current = rotation.new.author;
+        }
+    }
+    Ok(current)
+}
+
+fn rotation_bytes(old: &EndpointId, new: &EndpointId, issued_at: u64) -> Vec<u8> {
+    let mut message = ROTATION_CONTEXT.to_vec();
+    message.extend_from_slice(old.as_bytes());
+    message.extend_from_slice(new.as_bytes());
+    message.extend_from_slice(&issued_at.to_be_bytes());
+    message
+}
+
+fn generate_key() -> SecretKey {
+    SecretKey::from_bytes(&rand::random())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn read_key(path: &Path) -> Result<SecretKey> {
+    let contents = std::fs::read_to_string(path)?;
+    let bytes: [u8; 32] = hex_decode(contents.trim())
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} is not a hex-encoded secret key", path.display()),
+            )
+        })?;
+    Ok(SecretKey::from_bytes(&bytes))
+}
+
+/// Write a key readable by its owner only, replacing any existing file.
+fn write_key(path: &Path, key: &SecretKey) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // `mode` only applies to newly created files
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    let mut file = options.open(path)?;
+    file.write_all(hex_encode(&key.to_bytes()).as_bytes())?;
+    file.write_all(b"\n")?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn test_key_persists_across_open() {
+        let dir = temp_dir("keys");
+        let first = Keystore::open(&dir).unwrap().endpoint_id();
+        let second = Keystore::open(&dir).unwrap().endpoint_id();
+        assert_eq!(first, second);
+        assert_ne!(
+            first,
+            Keystore::open(temp_dir("keys")).unwrap().endpoint_id()
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join(KEY_FILE))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rotation_is_recorded_and_verifies() {
+        let dir = temp_dir("keys");
+        let mut keystore = Keystore::open(&dir).unwrap();
+        let original = keystore.endpoint_id();
+
+        let first = keystore.rotate().unwrap();
+        let second = keystore.rotate().unwrap();
+        assert_eq!(first.old.author, original);
+        assert_eq!(second.new.author, keystore.endpoint_id());
+        assert!(first.verify().is_ok());
+
+        let reopened = Keystore::open(&dir).unwrap();
+        assert_eq!(reopened.endpoint_id(), keystore.endpoint_id());
+        let rotations = reopened.rotations().unwrap();
+        assert_eq!(rotations, vec![first, second]);
+        assert_eq!(
+            follow_rotations(original, &rotations).unwrap(),
+            keystore.endpoint_id()
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_open_recovers_an_interrupted_rotation() {
+        let dir = temp_dir("keys");
+        let mut keystore = Keystore::open(&dir).unwrap();
+        let original = keystore.endpoint_id();
+
+        // Crashed after staging, before recording: the rotation never happened.
+        write_key(&dir.join(STAGED_KEY_FILE), &generate_key()).unwrap();
+        let reopened = Keystore::open(&dir).unwrap();
+        assert_eq!(reopened.endpoint_id(), original);
+        assert!(reopened.rotations().unwrap().is_empty());
+        assert!(!dir.join(STAGED_KEY_FILE).exists());
+
+        // Crashed after recording, before the rename: the rotation stands.
+        let rotation = keystore.rotate().unwrap();
+        let new_key = read_key(&dir.join(KEY_FILE)).unwrap();
+        write_key(&dir.join(STAGED_KEY_FILE), &new_key).unwrap();
+        write_key(&dir.join(KEY_FILE), &generate_key()).unwrap();
+        let reopened = Keystore::open(&dir).unwrap();
+        assert_eq!(reopened.endpoint_id(), rotation.new.author);
+        assert!(!dir.join(STAGED_KEY_FILE).exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_forged_rotation_is_rejected() {
+        let (old, new, mallory) = (generate_key(), generate_key(), generate_key());
+        let mut rotation = KeyRotation::sign(&old, &new, 1);
+        rotation.new = KeyRotation::sign(&old, &mallory, 1).new;
+        assert!(matches!(
+            rotation.verify(),
+            Err(BraidIrohError::BadSignature(_))
+        ));
+        assert!(follow_rotations(old.public(), &[rotation]).is_err());
+    }
+}
@@ -1,12 +1,44 @@
+pub mod acl;
+pub mod admin;
+pub mod builder;
+pub mod codec;
+pub mod dag;
 pub mod discovery;
+pub mod error;
+pub mod feed;
+pub mod keystore;
+pub mod merge;
+pub mod metrics;
 pub mod node;
+pub mod private;
 pub mod protocol;
 pub mod proxy;
+pub mod redact;
+pub mod signing;
+pub mod storage;
 pub mod subscription;
+pub mod sync;
+pub mod ticket;
 
+#[cfg(test)]
+mod test_util;
+
+pub use acl::{Acl, AclRule, Principal, Right, ACL_URL};
+pub use builder::BraidIrohNodeBuilder;
+pub use dag::*;
 pub use discovery::*;
+pub use error::{BraidIrohError, Result};
+pub use feed::*;
+pub use keystore::{KeyRotation, Keystore};
+pub use merge::*;
+pub use metrics::Metrics;
 pub use node::*;
+pub use private::ResourceSecret;
+pub use redact::RedactionPolicy;
+pub use signing::{Attribution, SignedUpdate};
+pub use storage::*;
 pub use subscription::*;
+pub use ticket::BraidTicket;
 
 use std::sync::Arc;
 
@@ -17,50 +49,40 @@ pub struct BraidIrohState {
     pub node_name: String,
 }
 
-/// Helper function to configure and spawn a Braid-Iroh Node
+/// Resource the demo node subscribes to on startup.
+pub const DEMO_DOC: &str = "/demo-doc";
+
+/// Spawn the demo node: an identity from the keystore in `data_dir(name)`
+/// (unless `secret_key_override` is given), in-memory storage, the proxy on
+/// `127.0.0.1:<port>` (8080 by default, with the `proxy` feature) and a
+/// subscription to `DEMO_DOC`, whose update stream is returned.
+///
+/// Anything else should use `BraidIrohNode::builder()`.
 pub async fn spawn_node(
     name: &str,
     port: Option<u16>,
     secret_key_override: Option<iroh::SecretKey>,
     discovery: DiscoveryConfig,
-) -> anyhow::Result<(Arc<BraidIrohState>, iroh_gossip::api::GossipReceiver)> {
-    let secret_key = if let Some(sk) = secret_key_override {
-        sk
-    } else {
-        get_or_create_secret_key(name).await
-    };
-
+) -> Result<(Arc<BraidIrohState>, UpdateStream)> {
     let proxy_port = port.unwrap_or(8080);
-    
     tracing::info!("[INIT] Spawning Node: {} | Port: {}", name, proxy_port);
 
-    #[cfg(feature = "proxy")]
-    let proxy_config = Some(crate::node::ProxyConfig {
-        listen_addr: format!("127.0.0.1:{}", proxy_port).parse().unwrap(),
-        default_peer: iroh::EndpointId::from_bytes(&[0u8; 32]).expect("Invalid placeholder key"), 
-    });
-    
-    #[cfg(not(feature = "proxy"))]
-    let proxy_config = None;
-
-    let peer = BraidIrohNode::spawn(BraidIrohConfig {
-        discovery,
-        secret_key: Some(secret_key),
-        proxy_config,
-    })
-    .await?;
+    let mut builder = BraidIrohNode::builder()
+        .discovery(discovery)
+        .subscribe(DEMO_DOC, vec![]);
+    builder = match secret_key_override {
+        Some(key) => builder.secret_key(key),
+        None => builder.keystore(data_dir(name)?),
+    };
+    if cfg!(feature = "proxy") {
+        builder = builder.proxy(std::net::SocketAddr::from(([127, 0, 0, 1], proxy_port)));
+    }
 
-    let peer = Arc::new(peer);
+    let peer = Arc::new(builder.spawn().await?);
     let peer_id = peer.node_id();
-    
     tracing::info!("[INIT] Node ID: {}", peer_id);
 
-    // Initial default subscription (generic, no bootsrap peers yet)
-    let rx = peer
-        .subscribe("/demo-doc", vec![])
-        .await
-        .map_err(|e| anyhow::anyhow!("Subscribe failed: {}", e))?;
-
+    let rx = peer.updates(DEMO_DOC);
     let state = Arc::new(BraidIrohState {
         peer,
         node_id: format!("{}", peer_id),
@@ -70,15 +92,47 @@ pub async fn spawn_node(
     Ok((state, rx))
 }
 
-/// Helper to predictably derive keys for "alice" and "bob" or hash other names
-pub async fn get_or_create_secret_key(name: &str) -> iroh::SecretKey {
-    if name == "alice" {
-        return iroh::SecretKey::from_bytes(&[1u8; 32]);
+/// Load the secret key of the node called `name` from its keystore in
+/// `data_dir(name)`, generating and saving a random one on first use.
+pub async fn get_or_create_secret_key(name: &str) -> Result<iroh::SecretKey> {
+    let keystore = Keystore::open(data_dir(name)?)?;
+    Ok(keystore.secret_key().clone())
+}
+
+/// Data directory of the node called `name`: `$BRAID_IROH_DATA_DIR/<name>`,
+/// or `.braid-iroh/<name>` under the working directory. `name` must be a
+/// single plain path component, so it can't point outside that directory.
+pub fn data_dir(name: &str) -> Result<std::path::PathBuf> {
+    let mut components = std::path::Path::new(name).components();
+    let plain = matches!(
+        (components.next(), components.next()),
+        (Some(std::path::Component::Normal(_)), None)
+    );
+    if !plain || name.contains(['/', '\\']) {
+        return Err(BraidIrohError::BadRequest(format!(
+            "node name '{}' must be a single path component",
+            name
+        )));
     }
-    if name == "bob" {
-        return iroh::SecretKey::from_bytes(&[2u8; 32]);
+    Ok(std::env::var_os("BRAID_IROH_DATA_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| std::path::PathBuf::from(".braid-iroh"))
+        .join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_dir_rejects_escaping_names() {
+        assert!(data_dir("alice").unwrap().ends_with("alice"));
+        for name in ["", ".", "..", "../x", "a/b", "/etc", "a\\b"] {
+            assert!(
+                matches!(data_dir(name), Err(BraidIrohError::BadRequest(_))),
+                "{:?} was accepted",
+                name
+            );
+        }
     }
-    
-    let hash = blake3::hash(name.as_bytes());
-    iroh::SecretKey::from_bytes(hash.as_bytes())
 }
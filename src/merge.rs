@@ -0,0 +1,603 @@
+//! Merge types: how concurrent versions combine into one state.
+//!
+//! A resource's `Merge-Type` header names the algorithm every peer uses to
+//! compute its current state from the version graph. Because the inputs
+//! (the graph) and the algorithm are the same everywhere, two peers that
+//! have seen the same versions always end up with identical state, no
+//! matter in which order the versions arrived.
+//!
+//! Built-in merge types:
+//! - `lww` — last writer wins: the greatest concurrent head's state.
+//! - `text` — replays every version's `text [start:end]` patches against
+//!   the text its author saw, so every branch's changes survive.
+
+use braid_http_rs::{Update, Version};
+use bytes::Bytes;
+use parking_lot::RwLock;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::dag::{compare_versions, VersionGraph};
+use crate::error::{BraidIrohError, Result};
+use crate::storage::version_string;
+
+/// Name of the merge type used when a resource doesn't declare one.
+pub const DEFAULT_MERGE_TYPE: &str = "lww";
+
+/// An algorithm for computing a resource's state from its version graph.
+pub trait MergeType: Send + Sync {
+    /// The `Merge-Type` header value this algorithm is registered under.
+    fn name(&self) -> &str;
+
+    /// Compute the resource body from every version in `graph`.
+    ///
+    /// Must be deterministic: equal graphs must give equal bodies.
+    fn merge(&self, graph: &VersionGraph) -> Result<Bytes>;
+}
+
+/// Last-writer-wins: the state of the greatest head of the graph.
+///
+/// Concurrent heads are ordered with `compare_versions`. A snapshot winner
+/// is its own state, byte for byte; a patch winner's state is its
+/// ancestors' `text` patches replayed in topological order.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LastWriterWins;
+
+impl MergeType for LastWriterWins {
+    fn name(&self) -> &str {
+        "lww"
+    }
+
+    fn merge(&self, graph: &VersionGraph) -> Result<Bytes> {
+        let Some(winner) = graph
+            .frontier()
+            .into_iter()
+            .max_by(|a, b| compare_versions(a, b))
+        else {
+            return Ok(Bytes::new());
+        };
+        let Some(node) = graph.get(&winner) else {
+            return Ok(Bytes::new());
+        };
+        if node.update.patches.as_ref().is_none_or(|p| p.is_empty()) {
+            return Ok(node.update.body.clone().unwrap_or_default());
+        }
+        let ancestors = graph.ancestors(&winner);
+        replay_text(graph, |key| ancestors.contains(key))
+    }
+}
+
+/// Text merge: every version's edits are kept.
+///
+/// Each character remembers the version that inserted it and the versions
+/// that deleted it. A `text [start:end]` patch is resolved against the text
+/// its author saw (only the characters of its ancestors), so its positions
+/// mean the same thing however many branches are merged; new text goes
+/// right after the character the author typed after. Snapshots replace the
+/// text their author saw, leaving concurrent insertions in place.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TextMerge;
+
+impl MergeType for TextMerge {
+    fn name(&self) -> &str {
+        "text"
+    }
+
+    fn merge(&self, graph: &VersionGraph) -> Result<Bytes> {
+        replay_text(graph, |_| true)
+    }
+}
+
+/// Replay the versions of `graph` that `include` accepts as text edits.
+fn replay_text(graph: &VersionGraph, include: impl Fn(&str) -> bool) -> Result<Bytes> {
+    let order: Vec<String> = graph
+        .topological_order()
+        .into_iter()
+        .filter(|key| include(key))
+        .collect();
+    let index: HashMap<&str, usize> = order
+        .iter()
+        .enumerate()
+        .map(|(i, key)| (key.as_str(), i))
+        .collect();
+
+    // Depth in the graph: a version is deeper than everything it has seen,
+    // so insertion IDs respect causality.
+    let mut depths = vec![0; order.len()];
+    for (i, key) in order.iter().enumerate() {
+        let Some(node) = graph.get(key) else { continue };
+        depths[i] = node
+            .parents
+            .iter()
+            .filter_map(|parent| graph.get(parent))
+            .filter_map(|parent| index.get(version_string(&parent.update).as_str()))
+            .map(|&p| depths[p] + 1)
+            .max()
+            .unwrap_or(0);
+    }
+    let mut by_version: Vec<usize> = (0..order.len()).collect();
+    by_version.sort_by(|&a, &b| compare_versions(&order[a], &order[b]));
+    let mut ranks = vec![0; order.len()];
+    for (rank, i) in by_version.into_iter().enumerate() {
+        ranks[i] = rank;
+    }
+
+    let mut text = TextState::default();
+    for (i, key) in order.iter().enumerate() {
+        let Some(node) = graph.get(key) else { continue };
+        let ancestors = graph.ancestors(key);
+        let seen: Vec<bool> = order.iter().map(|k| ancestors.contains(k)).collect();
+        let version = VersionEdit {
+            index: i,
+            depth: depths[i],
+            rank: Reverse(ranks[i]),
+            seen: &seen,
+        };
+        text.apply(&version, &node.update)?;
+    }
+    Ok(text.into_bytes())
+}
+
+/// The version whose update is being replayed.
+struct VersionEdit<'a> {
+    index: usize,
+    depth: usize,
+    /// Position in `compare_versions` order, reversed: of two concurrent
+    /// insertions at the same spot the smaller version comes first.
+    rank: Reverse<usize>,
+    /// Which versions (by replay index) its author had seen, itself
+    /// included.
+    seen: &'a [bool],
+}
+
+/// Orders insertions at the same spot: the greater ID goes first. Deeper
+/// versions and later characters of a version have greater IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct ItemId {
+    depth: usize,
+    rank: Reverse<usize>,
+    seq: usize,
+}
+
+/// One character, kept after deletion so later positions can still refer
+/// to it.
+#[derive(Debug)]
+struct Item {
+    ch: char,
+    id: ItemId,
+    inserted_by: usize,
+    deleted_by: Vec<usize>,
+}
+
+impl Item {
+    /// Whether the author of a version with `seen` saw this character.
+    fn visible_to(&self, seen: &[bool]) -> bool {
+        seen[self.inserted_by] && !self.deleted_by.iter().any(|&v| seen[v])
+    }
+}
+
+/// Text being rebuilt by replaying versions.
+#[derive(Debug, Default)]
+struct TextState {
+    items: Vec<Item>,
+    /// Characters inserted so far by the version being replayed.
+    seq: usize,
+}
+
+impl TextState {
+    /// Apply one version's patches, or its snapshot.
+    fn apply(&mut self, version: &VersionEdit, update: &Update) -> Result<()> {
+        self.seq = 0;
+        match &update.patches {
+            Some(patches) if !patches.is_empty() => {
+                for patch in patches {
+                    if patch.unit != "text" {
+                        return Err(BraidIrohError::Merge(format!(
+                            "unsupported patch unit '{}' for text merge",
+                            patch.unit
+                        )));
+                    }
+                    let (start, end) = parse_text_range(&patch.range)?;
+                    self.splice(version, start, end, &patch.content);
+                }
+            }
+            _ => {
+                let body = update.body.clone().unwrap_or_default();
+                self.splice(version, 0, usize::MAX, &body);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace `start..end` of the text `version` saw with `content`.
+    fn splice(&mut self, version: &VersionEdit, start: usize, end: usize, content: &[u8]) {
+        let visible: Vec<usize> = (0..self.items.len())
+            .filter(|&i| self.items[i].visible_to(version.seen))
+            .collect();
+        let start = start.min(visible.len());
+        let end = end.clamp(start, visible.len());
+
+        for &i in &visible[start..end] {
+            self.items[i].deleted_by.push(version.index);
+        }
+
+        let first = ItemId {
+            depth: version.depth,
+            rank: version.rank,
+            seq: self.seq,
+        };
+        let mut at = match start {
+            0 => 0,
+            _ => visible[start - 1] + 1,
+        };
+        while at < self.items.len() && self.items[at].id > first {
+            at += 1;
+        }
+        let inserted: Vec<Item> = String::from_utf8_lossy(content)
+            .chars()
+            .enumerate()
+            .map(|(n, ch)| Item {
+                ch,
+                id: ItemId {
+                    seq: self.seq + n,
+                    ..first
+                },
+                inserted_by: version.index,
+                deleted_by: Vec::new(),
+            })
+            .collect();
+        self.seq += inserted.len();
+        self.items.splice(at..at, inserted);
+    }
+
+    fn into_bytes(self) -> Bytes {
+        let text: String = self
+            .items
+            .into_iter()
+            .filter(|item| item.deleted_by.is_empty())
+            .map(|item| item.ch)
+            .collect();
+        Bytes::from(text)
+    }
+}
+
+/// Parse a text range `[start:end]` (or `[pos]` for a pure insertion).
+fn parse_text_range(range: &str) -> Result<(usize, usize)> {
+    let bad = || BraidIrohError::Merge(format!("bad text range '{}'", range));
+    let inner = range
+        .trim()
+        .strip_prefix('[')
+        .and_then(|r| r.strip_suffix(']'))
+        .ok_or_else(bad)?;
+    let parse = |n: &str| n.trim().parse::<usize>().map_err(|_| bad());
+    let (start, end) = match inner.split_once(':') {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => {
+            let pos = parse(inner)?;
+            (pos, pos)
+        }
+    };
+    if end < start {
+        return Err(bad());
+    }
+    Ok((start, end))
+}
+
+/// What a resolved state was computed from: the graph's heads and size.
+/// Versions are only ever added, so equal keys mean equal graphs.
+type ResolvedKey = (Vec<String>, usize);
+
+/// Merge types available on a node, keyed by `Merge-Type` name.
+#[derive(Clone)]
+pub struct MergeRegistry {
+    types: Arc<RwLock<HashMap<String, Arc<dyn MergeType>>>>,
+    /// The last state resolved per resource, so repeated reads of an
+    /// unchanged resource don't replay its history.
+    resolved: Arc<RwLock<HashMap<String, (ResolvedKey, Update)>>>,
+}
+
+impl Default for MergeRegistry {
+    fn default() -> Self {
+        let registry = Self {
+            types: Arc::new(RwLock::new(HashMap::new())),
+            resolved: Arc::new(RwLock::new(HashMap::new())),
+        };
+        registry.register(Arc::new(LastWriterWins));
+        registry.register(Arc::new(TextMerge));
+        registry
+    }
+}
+
+impl MergeRegistry {
+    /// A registry holding the built-in merge types.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Register a merge type, replacing any existing one with the same name.
+    pub fn register(&self, merge_type: Arc<dyn MergeType>) {
+        self.types
+            .write()
+            .insert(merge_type.name().to_string(), merge_type);
+        self.resolved.write().clear();
+    }
+
+    /// Look up a merge type by name.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn MergeType>> {
+        self.types.read().get(name).cloned()
+    }
+
+    /// Names of all registered merge types, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.types.read().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Compute the current state of the resource at `url` as a single
+    /// update.
+    ///
+    /// The merge type is the one declared by the resource's latest version
+    /// (or, failing that, by any version), defaulting to `lww`. The result
+    /// carries every head of the graph in its `version`. It is kept until
+    /// the graph changes, so only the first read after a new version pays
+    /// for the merge.
+    pub fn resolve(&self, url: &str, graph: &VersionGraph) -> Option<Update> {
+        let key = (graph.frontier(), graph.len());
+        if let Some((cached, state)) = self.resolved.read().get(url) {
+            if *cached == key {
+                return Some(state.clone());
+            }
+        }
+        let state = self.merge(graph)?;
+        self.resolved
+            .write()
+            .insert(url.to_string(), (key, state.clone()));
+        Some(state)
+    }
+
+    /// `resolve`, without the cache.
+    fn merge(&self, graph: &VersionGraph) -> Option<Update> {
+        let head = graph.latest()?.clone();
+        let frontier = graph.frontier();
+
+        // A single snapshot head is its own state; no replay needed.
+        let linear_snapshot =
+            frontier.len() == 1 && head.patches.as_ref().map(|p| p.is_empty()).unwrap_or(true);
+        if linear_snapshot {
+            return Some(head);
+        }
+
+        let name = head
+            .merge_type
+            .clone()
+            .or_else(|| graph.history().into_iter().find_map(|u| u.merge_type))
+            .unwrap_or_else(|| DEFAULT_MERGE_TYPE.to_string());
+        let merge_type = match self.get(&name) {
+            Some(m) => m,
+            None => {
+                tracing::warn!(merge_type = %name, "unknown merge type, using {}", DEFAULT_MERGE_TYPE);
+                self.get(DEFAULT_MERGE_TYPE)?
+            }
+        };
+
+        let body = match merge_type.merge(graph) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!(merge_type = %name, "merge failed, serving latest head: {}", e);
+                return Some(head);
+            }
+        };
+
+        let heads: HashSet<&String> = frontier.iter().collect();
+        let mut parents: Vec<String> = frontier
+            .iter()
+            .filter_map(|key| graph.get(key))
+            .flat_map(|node| node.parents.iter().cloned())
+            .filter(|p| !heads.contains(p))
+            .collect();
+        parents.sort();
+        parents.dedup();
+
+        let mut state = head;
+        state.version = frontier.into_iter().map(Version::String).collect();
+        state.parents = parents.into_iter().map(Version::String).collect();
+        state.merge_type = Some(name);
+        state.patches = None;
+        state.body = Some(body);
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use braid_http_rs::Patch;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn snapshot(version: &str, parents: &[&str], body: &str) -> Update {
+        let mut update = Update::snapshot(
+            Version::String(version.into()),
+            Bytes::from(body.to_string()),
+        );
+        update.parents = parents
+            .iter()
+            .map(|p| Version::String(p.to_string()))
+            .collect();
+        update
+    }
+
+    fn edit(version: &str, parents: &[&str], range: &str, text: &str) -> Update {
+        let mut update = snapshot(version, parents, "");
+        update.body = None;
+        update.merge_type = Some("text".into());
+        update.patches = Some(vec![Patch::new(
+            "text".to_string(),
+            range.to_string(),
+            Bytes::from(text.to_string()),
+        )]);
+        update
+    }
+
+    fn merged(registry: &MergeRegistry, updates: &[Update]) -> String {
+        let mut graph = VersionGraph::new();
+        for u in updates {
+            graph.insert(u.clone());
+        }
+        let state = registry.resolve("/doc", &graph).unwrap();
+        String::from_utf8(state.body.unwrap().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_text_range() {
+        assert_eq!(parse_text_range("[2:5]").unwrap(), (2, 5));
+        assert_eq!(parse_text_range("[3]").unwrap(), (3, 3));
+        assert!(parse_text_range("[5:2]").is_err());
+        assert!(parse_text_range("2:5").is_err());
+    }
+
+    #[test]
+    fn test_lww_picks_greatest_head() {
+        let registry = MergeRegistry::new();
+        let updates = [
+            snapshot("1", &[], "base"),
+            snapshot("alice-2", &["1"], "alice"),
+            snapshot("bob-2", &["1"], "bob"),
+        ];
+        assert_eq!(merged(&registry, &updates), "bob");
+    }
+
+    #[test]
+    fn test_lww_keeps_binary_snapshots_intact() {
+        let registry = MergeRegistry::new();
+        let mut base = snapshot("1", &[], "");
+        base.body = Some(Bytes::from_static(&[0x00, 0x01]));
+        let mut alice = snapshot("alice-2", &["1"], "");
+        alice.body = Some(Bytes::from_static(&[0xff, 0xfe, 0x00]));
+        let mut bob = snapshot("bob-2", &["1"], "");
+        bob.body = Some(Bytes::from_static(&[0xc3, 0x28, 0x80, 0xff]));
+
+        let mut graph = VersionGraph::new();
+        for update in [base, alice, bob] {
+            graph.insert(update);
+        }
+        let state = registry.resolve("/blob", &graph).unwrap();
+        assert_eq!(state.body.unwrap().as_ref(), &[0xc3, 0x28, 0x80, 0xff]);
+    }
+
+    #[test]
+    fn test_text_merge_keeps_concurrent_edits() {
+        let registry = MergeRegistry::new();
+        let mut base = snapshot("0", &[], "hello world");
+        base.merge_type = Some("text".into());
+        let updates = [
+            base,
+            edit("alice-1", &["0"], "[0:5]", "HELLO"),
+            edit("bob-1", &["0"], "[11:11]", "!"),
+        ];
+        assert_eq!(merged(&registry, &updates), "HELLO world!");
+    }
+
+    #[test]
+    fn test_text_merge_converges_regardless_of_arrival() {
+        let registry = MergeRegistry::new();
+        let mut base = snapshot("0", &[], "abc");
+        base.merge_type = Some("text".into());
+        let updates = vec![
+            base,
+            edit("alice-1", &["0"], "[1:1]", "X"),
+            edit("bob-1", &["0"], "[1:1]", "Y"),
+            edit("carol-2", &["alice-1"], "[0:1]", ""),
+        ];
+        let mut reversed = updates.clone();
+        reversed.reverse();
+
+        let forward = merged(&registry, &updates);
+        assert_eq!(forward, merged(&registry, &reversed));
+        assert_eq!(forward, "XYbc");
+    }
+
+    #[test]
+    fn test_text_merge_keeps_intent_across_three_branches() {
+        let registry = MergeRegistry::new();
+        let mut base = snapshot("0", &[], "abc");
+        base.merge_type = Some("text".into());
+        // Three concurrent edits of "abc": alice deletes "ab", bob types
+        // "Y" between "b" and "c", carol types "Z" between "a" and "b".
+        // Then dave, having seen alice and carol ("Zc"), types "W" at the
+        // end, and erin, having seen bob ("abYc"), replaces "c" with "!".
+        let updates = vec![
+            base,
+            edit("alice-1", &["0"], "[0:2]", ""),
+            edit("bob-1", &["0"], "[2:2]", "Y"),
+            edit("carol-1", &["0"], "[1:1]", "Z"),
+            edit("dave-2", &["alice-1", "carol-1"], "[2:2]", "W"),
+            edit("erin-2", &["bob-1"], "[3:4]", "!"),
+        ];
+        let mut reversed = updates.clone();
+        reversed.reverse();
+
+        // Z stays before where "b" was and Y after it; W follows "c",
+        // which erin replaced with "!".
+        assert_eq!(merged(&registry, &updates), "ZY!W");
+        assert_eq!(merged(&registry, &reversed), "ZY!W");
+    }
+
+    #[test]
+    fn test_resolve_reuses_state_until_the_graph_changes() {
+        struct Counting(Arc<AtomicUsize>);
+        impl MergeType for Counting {
+            fn name(&self) -> &str {
+                "counting"
+            }
+            fn merge(&self, _graph: &VersionGraph) -> Result<Bytes> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(Bytes::new())
+            }
+        }
+
+        let merges = Arc::new(AtomicUsize::new(0));
+        let registry = MergeRegistry::new();
+        registry.register(Arc::new(Counting(merges.clone())));
+        let mut graph = VersionGraph::new();
+        for version in ["a", "b"] {
+            let mut update = snapshot(version, &[], version);
+            update.merge_type = Some("counting".into());
+            graph.insert(update);
+        }
+
+        registry.resolve("/doc", &graph).unwrap();
+        registry.resolve("/doc", &graph).unwrap();
+        assert_eq!(merges.load(Ordering::SeqCst), 1);
+        registry.resolve("/other", &graph).unwrap();
+        assert_eq!(merges.load(Ordering::SeqCst), 2);
+
+        graph.insert(snapshot("c", &["a"], "c"));
+        registry.resolve("/doc", &graph).unwrap();
+        assert_eq!(merges.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_registry_custom_type() {
+        struct Constant;
+        impl MergeType for Constant {
+            fn name(&self) -> &str {
+                "constant"
+            }
+            fn merge(&self, _graph: &VersionGraph) -> Result<Bytes> {
+                Ok(Bytes::from_static(b"same"))
+            }
+        }
+
+        let registry = MergeRegistry::new();
+        registry.register(Arc::new(Constant));
+        assert_eq!(registry.names(), vec!["constant", "lww", "text"]);
+
+        let mut a = snapshot("a", &[], "a");
+        a.merge_type = Some("constant".into());
+        let mut b = snapshot("b", &[], "b");
+        b.merge_type = Some("constant".into());
+        assert_eq!(merged(&registry, &[a, b]), "same");
+    }
+}
@@ -0,0 +1,442 @@
+//! Node metrics.
+//!
+//! A small, lock-free registry of counters, gauges and histograms covering
+//! gossip traffic, storage, the HTTP/3 handlers and the proxy bridge. One
+//! `Metrics` instance is shared by everything a node owns; read it directly
+//! via `BraidIrohNode::metrics`, or scrape it in Prometheus text format from
+//! `/_braid/metrics` on the proxy listener.
+
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Histogram bucket bounds for request latencies, in seconds.
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A monotonically increasing count.
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that can go up and down.
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, delta: i64) {
+        self.0.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A distribution of durations over fixed buckets.
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    /// Per-bucket (non-cumulative) counts; the last slot is `+Inf`.
+    buckets: Box<[AtomicU64]>,
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    /// A histogram with the given upper bounds (in seconds, ascending).
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let slot = self
+            .bounds
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.buckets[slot].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of observations.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Sum of all observations.
+    pub fn sum(&self) -> Duration {
+        Duration::from_micros(self.sum_micros.load(Ordering::Relaxed))
+    }
+
+    /// Cumulative counts per upper bound, ending with `+Inf`.
+    pub fn buckets(&self) -> Vec<(f64, u64)> {
+        let mut total = 0;
+        self.bounds
+            .iter()
+            .copied()
+            .chain(std::iter::once(f64::INFINITY))
+            .zip(self.buckets.iter())
+            .map(|(bound, count)| {
+                total += count.load(Ordering::Relaxed);
+                (bound, total)
+            })
+            .collect()
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(LATENCY_BUCKETS)
+    }
+}
+
+/// A counter split by a fixed set of labels (e.g. method and status).
+#[derive(Debug)]
+pub struct LabeledCounter {
+    labels: &'static [&'static str],
+    values: RwLock<BTreeMap<Vec<String>, u64>>,
+}
+
+impl LabeledCounter {
+    pub fn new(labels: &'static [&'static str]) -> Self {
+        Self {
+            labels,
+            values: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Increment the series for `values`, given in label order.
+    pub fn inc(&self, values: &[&str]) {
+        debug_assert_eq!(values.len(), self.labels.len());
+        let key: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        *self.values.write().entry(key).or_insert(0) += 1;
+    }
+
+    /// The count for `values`, given in label order.
+    pub fn get(&self, values: &[&str]) -> u64 {
+        let key: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        self.values.read().get(&key).copied().unwrap_or(0)
+    }
+
+    /// Sum across all series.
+    pub fn total(&self) -> u64 {
+        self.values.read().values().sum()
+    }
+}
+
+/// Every metric a node records.
+#[derive(Debug)]
+pub struct Metrics {
+    /// Updates broadcast on gossip topics.
+    pub gossip_updates_sent: Counter,
+    /// Updates received on gossip topics.
+    pub gossip_updates_received: Counter,
+    /// Payload bytes broadcast on gossip topics.
+    pub gossip_bytes_sent: Counter,
+    /// Payload bytes received on gossip topics.
+    pub gossip_bytes_received: Counter,
+    /// Gossip messages that could not be decoded.
+    pub gossip_decode_errors: Counter,
+    /// Gossip updates dropped because their signature did not verify.
+    pub gossip_bad_signatures: Counter,
+    /// Gossip topics currently joined.
+    pub gossip_topics: Gauge,
+    /// Direct gossip neighbors, summed across topics.
+    pub gossip_neighbors: Gauge,
+    /// Requests and updates refused by the ACL.
+    pub acl_denials: Counter,
+    /// Updates accepted into the store.
+    pub updates_stored: Counter,
+    /// Updates ignored because their version was already stored.
+    pub updates_duplicate: Counter,
+    /// Updates the store failed to persist.
+    pub storage_errors: Counter,
+    /// HTTP/3 requests served, by method and status.
+    pub http_requests: LabeledCounter,
+    /// Time to produce HTTP/3 response headers.
+    pub http_request_duration: Histogram,
+    /// Requests forwarded by the proxy bridge.
+    pub proxy_requests: Counter,
+    /// Proxy requests that failed before a response was produced.
+    pub proxy_errors: Counter,
+    /// Time to forward a proxy request and receive the response.
+    pub proxy_request_duration: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            gossip_updates_sent: Counter::default(),
+            gossip_updates_received: Counter::default(),
+            gossip_bytes_sent: Counter::default(),
+            gossip_bytes_received: Counter::default(),
+            gossip_decode_errors: Counter::default(),
+            gossip_bad_signatures: Counter::default(),
+            gossip_topics: Gauge::default(),
+            gossip_neighbors: Gauge::default(),
+            acl_denials: Counter::default(),
+            updates_stored: Counter::default(),
+            updates_duplicate: Counter::default(),
+            storage_errors: Counter::default(),
+            http_requests: LabeledCounter::new(&["method", "status"]),
+            http_request_duration: Histogram::default(),
+            proxy_requests: Counter::default(),
+            proxy_errors: Counter::default(),
+            proxy_request_duration: Histogram::default(),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Render every metric in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        let counters: [(&str, &str, &Counter); 12] = [
+            (
+                "braid_gossip_updates_sent_total",
+                "Updates broadcast on gossip topics.",
+                &self.gossip_updates_sent,
+            ),
+            (
+                "braid_gossip_updates_received_total",
+                "Updates received on gossip topics.",
+                &self.gossip_updates_received,
+            ),
+            (
+                "braid_gossip_bytes_sent_total",
+                "Payload bytes broadcast on gossip topics.",
+                &self.gossip_bytes_sent,
+            ),
+            (
+                "braid_gossip_bytes_received_total",
+                "Payload bytes received on gossip topics.",
+                &self.gossip_bytes_received,
+            ),
+            (
+                "braid_gossip_decode_errors_total",
+                "Gossip messages that could not be decoded.",
+                &self.gossip_decode_errors,
+            ),
+            (
+                "braid_gossip_bad_signatures_total",
+                "Gossip updates dropped because their signature did not verify.",
+                &self.gossip_bad_signatures,
+            ),
+            (
+                "braid_acl_denials_total",
+                "Requests and updates refused by the ACL.",
+                &self.acl_denials,
+            ),
+            (
+                "braid_updates_stored_total",
+                "Updates accepted into the store.",
+                &self.updates_stored,
+            ),
+            (
+                "braid_updates_duplicate_total",
+                "Updates ignored because their version was already stored.",
+                &self.updates_duplicate,
+            ),
+            (
+                "braid_storage_errors_total",
+                "Updates the store failed to persist.",
+                &self.storage_errors,
+            ),
+            (
+                "braid_proxy_requests_total",
+                "Requests forwarded by the proxy bridge.",
+                &self.proxy_requests,
+            ),
+            (
+                "braid_proxy_errors_total",
+                "Proxy requests that failed.",
+                &self.proxy_errors,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            header(&mut out, name, help, "counter");
+            let _ = writeln!(out, "{} {}", name, counter.get());
+        }
+
+        for (name, help, gauge) in [
+            (
+                "braid_gossip_topics",
+                "Gossip topics currently joined.",
+                &self.gossip_topics,
+            ),
+            (
+                "braid_gossip_neighbors",
+                "Direct gossip neighbors, summed across topics.",
+                &self.gossip_neighbors,
+            ),
+        ] {
+            header(&mut out, name, help, "gauge");
+            let _ = writeln!(out, "{} {}", name, gauge.get());
+        }
+
+        labeled(
+            &mut out,
+            "braid_http_requests_total",
+            "HTTP/3 requests served, by method and status.",
+            &self.http_requests,
+        );
+
+        histogram(
+            &mut out,
+            "braid_http_request_duration_seconds",
+            "Time to produce HTTP/3 response headers.",
+            &self.http_request_duration,
+        );
+        histogram(
+            &mut out,
+            "braid_proxy_request_duration_seconds",
+            "Time to forward a proxy request and receive the response.",
+            &self.proxy_request_duration,
+        );
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn labeled(out: &mut String, name: &str, help: &str, counter: &LabeledCounter) {
+    header(out, name, help, "counter");
+    for (values, count) in counter.values.read().iter() {
+        let labels: Vec<String> = counter
+            .labels
+            .iter()
+            .zip(values)
+            .map(|(label, value)| format!("{}=\"{}\"", label, escape_label(value)))
+            .collect();
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), count);
+    }
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    header(out, name, help, "histogram");
+    for (bound, count) in histogram.buckets() {
+        let le = if bound.is_infinite() {
+            "+Inf".to_string()
+        } else {
+            bound.to_string()
+        };
+        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, count);
+    }
+    let _ = writeln!(out, "{}_sum {}", name, histogram.sum().as_secs_f64());
+    let _ = writeln!(out, "{}_count {}", name, histogram.count());
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_and_gauge() {
+        let metrics = Metrics::new();
+        metrics.gossip_updates_sent.inc();
+        metrics.gossip_bytes_sent.add(42);
+        metrics.gossip_neighbors.inc();
+        metrics.gossip_neighbors.inc();
+        metrics.gossip_neighbors.dec();
+
+        assert_eq!(metrics.gossip_updates_sent.get(), 1);
+        assert_eq!(metrics.gossip_bytes_sent.get(), 42);
+        assert_eq!(metrics.gossip_neighbors.get(), 1);
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let histogram = Histogram::new(&[0.01, 0.1]);
+        histogram.observe(Duration::from_millis(5));
+        histogram.observe(Duration::from_millis(50));
+        histogram.observe(Duration::from_secs(1));
+
+        assert_eq!(
+            histogram.buckets(),
+            vec![(0.01, 1), (0.1, 2), (f64::INFINITY, 3)]
+        );
+        assert_eq!(histogram.count(), 3);
+        assert_eq!(histogram.sum(), Duration::from_millis(1055));
+    }
+
+    #[test]
+    fn test_labeled_counter() {
+        let requests = LabeledCounter::new(&["method", "status"]);
+        requests.inc(&["GET", "200"]);
+        requests.inc(&["GET", "200"]);
+        requests.inc(&["PUT", "400"]);
+
+        assert_eq!(requests.get(&["GET", "200"]), 2);
+        assert_eq!(requests.get(&["PUT", "200"]), 0);
+        assert_eq!(requests.total(), 3);
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let metrics = Metrics::new();
+        metrics.updates_stored.add(3);
+        metrics.http_requests.inc(&["GET", "404"]);
+        metrics
+            .http_request_duration
+            .observe(Duration::from_millis(2));
+
+        let text = metrics.render_prometheus();
+        assert!(text
+            .contains("# TYPE braid_updates_stored_total counter\nbraid_updates_stored_total 3\n"));
+        assert!(text.contains("braid_http_requests_total{method=\"GET\",status=\"404\"} 1\n"));
+        assert!(text.contains("braid_http_request_duration_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(text.contains("braid_http_request_duration_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(text.contains("braid_http_request_duration_seconds_count 1\n"));
+    }
+}
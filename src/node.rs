@@ -4,18 +4,39 @@
 //! Braid protocol handler. This is the type you create to participate
 //! in the P2P Braid network.
 
-use braid_http_rs::Update;
+use braid_http_rs::{Update, Version};
+use bytes::Bytes;
 
-use iroh::{protocol::Router, Endpoint, EndpointAddr, EndpointId, SecretKey};
-use iroh_gossip::api::GossipReceiver;
+use iroh::address_lookup::memory::MemoryLookup;
+use iroh::{protocol::Router, Endpoint, EndpointAddr, EndpointId, RelayMode, SecretKey};
+use futures::StreamExt;
+use iroh_gossip::api::{Event, GossipReceiver};
 use iroh_gossip::net::Gossip;
+use iroh_h3_client::IrohH3Client;
+use parking_lot::Mutex;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::OnceCell;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
+use crate::acl::{Acl, AclManager, Principal, Right, ACL_URL};
+use crate::builder::BraidIrohNodeBuilder;
+use crate::codec;
 use crate::discovery::{DiscoveryConfig, MockDiscoveryMap};
-use crate::protocol::{self, BraidAppState};
-use crate::subscription::SubscriptionManager;
+use crate::error::{BraidIrohError, Result};
+use crate::feed::{UpdateFeed, UpdateStream};
+use crate::merge::{MergeRegistry, MergeType};
+use crate::metrics::Metrics;
+use crate::private::ResourceSecret;
+use crate::protocol::{self, assign_version, BraidAppState};
+use crate::redact::RedactionPolicy;
+use crate::signing::{Attribution, SignedUpdate};
+use crate::storage::{list_directory, version_string, ResourceStore, StorageConfig};
+use crate::subscription::{SubscriptionManager, TopicEvent, TopicHook};
+use crate::sync::{self, CatchUpSlots};
+use crate::ticket::BraidTicket;
 
 /// ALPN protocol identifier for Braid-over-H3.
 /// Peers negotiate this during the QUIC handshake.
@@ -33,6 +54,24 @@ pub struct BraidIrohConfig {
 
     /// Optional configuration for the TCP proxy bridge.
     pub proxy_config: Option<ProxyConfig>,
+
+    /// Where resources and their history are stored.
+    pub storage: StorageConfig,
+
+    /// How resource bodies appear in trace logs.
+    pub redaction: RedactionPolicy,
+
+    /// Access rules to start with. An ACL already stored at `ACL_URL`
+    /// takes precedence.
+    pub acl: Acl,
+
+    /// Which relay servers the endpoint uses to reach peers it can't
+    /// connect to directly.
+    pub relay_mode: RelayMode,
+
+    /// Merge types to register alongside the built-in ones. They are in
+    /// place before the node serves its first request.
+    pub merge_types: Vec<Arc<dyn MergeType>>,
 }
 
 
@@ -41,8 +80,15 @@ pub struct BraidIrohConfig {
 pub struct ProxyConfig {
     /// Local address to listen on (e.g. 127.0.0.1:8080).
     pub listen_addr: std::net::SocketAddr,
-    /// Default peer ID to forward requests to.
-    pub default_peer: EndpointId,
+    /// Bearer token required by the admin routes under `/_braid/`. `None`
+    /// leaves them off; an empty or blank token is rejected by `spawn`.
+    pub admin_token: Option<String>,
+    /// Peer to forward requests naming no peer to. `None` serves them from
+    /// this node. Requests can pick another peer per request; see `proxy`.
+    pub default_peer: Option<EndpointId>,
+    /// Serve requests for the default peer from this node when it already
+    /// has the resource, instead of forwarding them.
+    pub serve_local: bool,
 }
 
 impl Default for BraidIrohConfig {
@@ -51,40 +97,190 @@ impl Default for BraidIrohConfig {
             discovery: DiscoveryConfig::Mock(MockDiscoveryMap::new()),
             secret_key: None,
             proxy_config: None,
+            storage: StorageConfig::Memory,
+            redaction: RedactionPolicy::default(),
+            acl: Acl::open(),
+            relay_mode: RelayMode::Default,
+            merge_types: Vec::new(),
         }
     }
 }
 
+/// Background tasks applying incoming gossip, keyed by subscribed URL.
+type IngestTasks = Arc<Mutex<HashMap<String, JoinHandle<()>>>>;
+
 /// A Braid-capable P2P peer. Holds the iroh endpoint, gossip, and
 /// subscription state. Create one per peer identity.
+///
+/// Dropping the node cancels its shutdown token, so the same teardown as
+/// `shutdown` runs in the background.
 pub struct BraidIrohNode {
     endpoint: Endpoint,
-    #[allow(dead_code)]
+    /// Addresses learned out of band, from redeemed tickets. Consulted by
+    /// the endpoint alongside the configured discovery.
+    address_book: MemoryLookup,
+    state: BraidAppState,
+    topics: TopicControl,
+    /// Cancelled when the node starts shutting down.
+    shutdown: CancellationToken,
+    teardown: Arc<Teardown>,
+}
+
+/// Joins and leaves a node's gossip topics, running one ingest task per
+/// joined topic. Shared by the node and the proxy's admin routes.
+#[derive(Clone)]
+pub struct TopicControl {
+    node_id: EndpointId,
+    state: BraidAppState,
+    /// HTTP/3 client used to fetch missing history from neighbors.
+    client: IrohH3Client,
+    /// Background tasks applying incoming gossip, one per subscribed URL.
+    ingest_tasks: IngestTasks,
+    /// Catch-ups the ingest tasks are running.
+    catch_ups: CatchUpSlots,
+    shutdown: CancellationToken,
+}
+
+impl TopicControl {
+    /// Join a resource's topic and start applying its gossip; see
+    /// `BraidIrohNode::subscribe`.
+    pub async fn join(&self, url: &str, bootstrap: Vec<EndpointId>) -> Result<UpdateStream> {
+        if self.shutdown.is_cancelled() {
+            return Err(BraidIrohError::ShuttingDown);
+        }
+        let normalized = SubscriptionManager::normalize_url(url);
+        tracing::debug!(bootstrap = ?bootstrap, "subscribing");
+
+        let stream = self.state.feed.subscribe(&normalized);
+
+        if self.ingest_tasks.lock().contains_key(&normalized) {
+            if !bootstrap.is_empty() {
+                self.state.subscriptions.join_peers(&normalized, bootstrap).await?;
+            }
+            return Ok(stream);
+        }
+
+        let (_sender, receiver) = self.state.subscriptions.subscribe(url, bootstrap).await?;
+        let span = tracing::info_span!(
+            "ingest",
+            node = %self.node_id,
+            url = %normalized,
+            topic = %self.state.subscriptions.topic_id(&normalized),
+        );
+        let task = tokio::spawn(
+            ingest_gossip(
+                self.state.clone(),
+                self.client.clone(),
+                self.catch_ups.clone(),
+                normalized.clone(),
+                receiver,
+            )
+            .instrument(span),
+        );
+        if let Some(previous) = self.ingest_tasks.lock().insert(normalized, task) {
+            previous.abort();
+        }
+        tracing::info!("subscribed");
+        Ok(stream)
+    }
+
+    /// Stop applying a resource's gossip and leave its topic; see
+    /// `BraidIrohNode::unsubscribe`.
+    pub async fn leave(&self, url: &str) -> bool {
+        let normalized = SubscriptionManager::normalize_url(url);
+        if let Some(task) = self.ingest_tasks.lock().remove(&normalized) {
+            task.abort();
+        }
+        let left = self.state.subscriptions.unsubscribe(&normalized).await;
+        if left {
+            tracing::info!("unsubscribed");
+        }
+        left
+    }
+}
+
+/// Everything a shutdown has to stop. Shared between `shutdown` and the
+/// task that runs it when the token is cancelled elsewhere; whichever gets
+/// there first does the work and the other waits for the outcome.
+struct Teardown {
     router: Router,
-    subscription_mgr: Arc<SubscriptionManager>,
-    resources: Arc<RwLock<HashMap<String, Vec<Update>>>>,
+    state: BraidAppState,
+    ingest_tasks: IngestTasks,
+    proxy_task: Mutex<Option<JoinHandle<()>>>,
+    outcome: OnceCell<std::result::Result<(), String>>,
+}
+
+impl Teardown {
+    async fn run(&self) -> Result<()> {
+        self.outcome
+            .get_or_init(|| self.stop())
+            .await
+            .clone()
+            .map_err(BraidIrohError::Endpoint)
+    }
+
+    async fn stop(&self) -> std::result::Result<(), String> {
+        tracing::info!("shutting down");
+
+        // The proxy stopped accepting when the token was cancelled; let
+        // the requests it is still serving finish.
+        let proxy_task = self.proxy_task.lock().take();
+        if let Some(task) = proxy_task {
+            let _ = task.await;
+        }
+
+        // Stop applying gossip, then leave every topic. Leaving waits for
+        // broadcasts that are still being sent.
+        for (_, task) in self.ingest_tasks.lock().drain() {
+            task.abort();
+        }
+        let topics = self.state.subscriptions.unsubscribe_all().await;
+        tracing::debug!(topics, "left gossip topics");
+
+        if let Err(e) = self.state.store.flush() {
+            tracing::error!("failed to flush storage: {}", e);
+        }
+
+        self.router.shutdown().await.map_err(|e| e.to_string())?;
+        tracing::info!("shut down");
+        Ok(())
+    }
 }
 
 impl BraidIrohNode {
+    /// Configure a node step by step; see `BraidIrohNodeBuilder`.
+    pub fn builder() -> BraidIrohNodeBuilder {
+        BraidIrohNodeBuilder::new()
+    }
+
     /// Spawn a new Braid-over-Iroh node.
     ///
     /// This sets up the iroh endpoint, starts the gossip protocol,
     /// mounts the Braid-HTTP Axum routes via `IrohAxum`, and begins
     /// accepting incoming connections.
-    pub async fn spawn(config: BraidIrohConfig) -> anyhow::Result<Self> {
+    pub async fn spawn(config: BraidIrohConfig) -> Result<Self> {
+        if let Some(token) = config.proxy_config.as_ref().and_then(|p| p.admin_token.as_deref()) {
+            crate::admin::check_token(token)?;
+        }
+
         // 1. Build the iroh endpoint with discovery + Braid ALPN
         let mut builder = Endpoint::builder().alpns(vec![
             BRAID_H3_ALPN.to_vec(),
             iroh_gossip::net::GOSSIP_ALPN.to_vec(),
         ]);
 
+        builder = builder.relay_mode(config.relay_mode);
+
         // Apply optional secret key
         if let Some(key) = config.secret_key {
             builder = builder.secret_key(key);
         }
 
-        // Apply discovery logic
-        match config.discovery {
+        // Apply discovery logic. Ticket addresses go in the address book
+        // whichever discovery is used.
+        let address_book = MemoryLookup::new();
+        builder = builder.address_lookup(address_book.clone());
+        match config.discovery.clone() {
             DiscoveryConfig::Mock(map) => {
                 builder = builder.address_lookup(map);
             }
@@ -94,7 +290,10 @@ impl BraidIrohNode {
             }
         }
 
-        let endpoint = builder.bind().await?;
+        let endpoint = builder
+            .bind()
+            .await
+            .map_err(|e| BraidIrohError::Endpoint(e.to_string()))?;
 
         tracing::info!(id = %endpoint.id(), "iroh endpoint bound");
 
@@ -103,46 +302,106 @@ impl BraidIrohNode {
         let gossip = Gossip::builder().spawn(endpoint.clone());
 
         // 3. Build shared state
-        let resources: Arc<RwLock<HashMap<String, Vec<Update>>>> =
-            Arc::new(RwLock::new(HashMap::new()));
+        let store = config.storage.open()?;
 
-        let subscription_mgr = Arc::new(SubscriptionManager::new(gossip.clone()));
+        let metrics = Arc::new(Metrics::new());
+        let subscription_mgr = Arc::new(SubscriptionManager::with_metrics(
+            gossip.clone(),
+            endpoint.secret_key().clone(),
+            metrics.clone(),
+        ));
 
+        let merges = MergeRegistry::new();
+        for merge_type in config.merge_types {
+            merges.register(merge_type);
+        }
         let app_state = BraidAppState {
-            subscriptions: subscription_mgr.clone(),
-            resources: resources.clone(),
+            subscriptions: subscription_mgr,
+            store,
+            feed: UpdateFeed::new(),
+            merges,
+            redaction: config.redaction,
+            metrics: metrics.clone(),
+            acl: AclManager::new(endpoint.id(), config.acl),
         };
+        app_state.reload_acl();
 
         // 4. Mount the Braid protocol handler on the iroh router
-        let braid_handler = protocol::build_protocol_handler(app_state);
+        let braid_handler = protocol::build_protocol_handler(app_state.clone());
 
         let router = Router::builder(endpoint.clone())
-            .accept(BRAID_H3_ALPN.to_vec(), braid_handler)
-            .accept(iroh_gossip::net::GOSSIP_ALPN.to_vec(), gossip.clone())
+            .accept(BRAID_H3_ALPN, braid_handler)
+            .accept(iroh_gossip::net::GOSSIP_ALPN, gossip.clone())
             .spawn();
 
+        let shutdown = CancellationToken::new();
+        let ingest_tasks: IngestTasks = Arc::new(Mutex::new(HashMap::new()));
+        let topics = TopicControl {
+            node_id: endpoint.id(),
+            state: app_state.clone(),
+            client: IrohH3Client::new(endpoint.clone(), BRAID_H3_ALPN.to_vec()),
+            ingest_tasks: ingest_tasks.clone(),
+            catch_ups: CatchUpSlots::default(),
+            shutdown: shutdown.clone(),
+        };
+
         // 5. Start TCP Proxy if configured (Phase 4)
         #[cfg(feature = "proxy")]
-        if let Some(proxy_conf) = config.proxy_config {
+        let proxy_task = config.proxy_config.map(|proxy_conf| {
             let endpoint_clone = endpoint.clone();
+            let app_state = app_state.clone();
+            let admin = proxy_conf.admin_token.clone().map(|token| crate::admin::AdminState {
+                token: Arc::from(token),
+                endpoint: endpoint.clone(),
+                discovery: config.discovery.clone(),
+                state: app_state.clone(),
+                topics: topics.clone(),
+            });
+            let shutdown = shutdown.clone();
             tokio::spawn(async move {
                 if let Err(e) = crate::proxy::bridge::start_proxy(
                     &endpoint_clone,
-                    proxy_conf.listen_addr,
-                    proxy_conf.default_peer,
+                    proxy_conf,
+                    app_state,
+                    admin,
+                    shutdown,
                 )
                 .await
                 {
                     tracing::error!("TCP Proxy failed: {}", e);
                 }
+            })
+        });
+        #[cfg(not(feature = "proxy"))]
+        let proxy_task = None;
+
+        // 6. Tear everything down once the shutdown token is cancelled,
+        // whoever cancels it.
+        let teardown = Arc::new(Teardown {
+            router,
+            state: app_state.clone(),
+            ingest_tasks,
+            proxy_task: Mutex::new(proxy_task),
+            outcome: OnceCell::new(),
+        });
+        {
+            let teardown = teardown.clone();
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                shutdown.cancelled().await;
+                if let Err(e) = teardown.run().await {
+                    tracing::error!("shutdown failed: {}", e);
+                }
             });
         }
 
         Ok(Self {
             endpoint,
-            router,
-            subscription_mgr,
-            resources,
+            address_book,
+            state: app_state,
+            topics,
+            shutdown,
+            teardown,
         })
     }
 
@@ -152,115 +411,337 @@ impl BraidIrohNode {
     }
 
     /// Full address info for this node (id + addresses + relay).
-    pub async fn node_addr(&self) -> anyhow::Result<EndpointAddr> {
+    pub async fn node_addr(&self) -> Result<EndpointAddr> {
         Ok(self.endpoint.addr())
     }
 
     /// Subscribe to a resource URL on the gossip network.
-    /// Returns a receiver stream of gossip events for this resource.
+    ///
+    /// The node spawns a background task that decodes every `Update`
+    /// received on the resource's topic and stores it (ignoring versions it
+    /// already has). Each neighbor met on the topic is asked for the
+    /// history we are missing, so a late joiner converges without manual
+    /// `GET`s. Returns a stream of all updates accepted for the
+    /// resource from now on, whether they arrive via gossip, HTTP/3 or a
+    /// local `put`. Subscribing again to the same URL only joins the extra
+    /// bootstrap peers.
+    #[tracing::instrument(
+        name = "subscribe",
+        skip_all,
+        fields(
+            node = %self.endpoint.id(),
+            url = %SubscriptionManager::normalize_url(url),
+            topic = %self.state.subscriptions.topic_id(url),
+        )
+    )]
     pub async fn subscribe(
         &self,
         url: &str,
         bootstrap: Vec<EndpointId>,
-    ) -> anyhow::Result<GossipReceiver> {
-        println!("[NODE] Subscribing to {} with {} bootstrap peers", url, bootstrap.len());
-        for peer in &bootstrap {
-            println!("[NODE]   bootstrap: {}", peer);
+    ) -> Result<UpdateStream> {
+        self.topics.join(url, bootstrap).await
+    }
+
+    /// Subscribe to a private resource. Its topic is derived from `secret`
+    /// instead of the URL alone, and every payload broadcast on it is
+    /// encrypted with the secret, so only peers holding the same secret can
+    /// find the topic or read its updates.
+    ///
+    /// Fails with `Sealed` if the resource is already subscribed under
+    /// another topic (publicly, or with a different secret); unsubscribe
+    /// first.
+    pub async fn subscribe_private(
+        &self,
+        url: &str,
+        secret: ResourceSecret,
+        bootstrap: Vec<EndpointId>,
+    ) -> Result<UpdateStream> {
+        let subscriptions = &self.state.subscriptions;
+        let same_topic = subscriptions.secret(url).as_ref() == Some(&secret);
+        if subscriptions.is_subscribed(url).await && !same_topic {
+            return Err(BraidIrohError::Sealed(format!(
+                "{} is already subscribed under another topic",
+                SubscriptionManager::normalize_url(url)
+            )));
         }
-        let (_sender, receiver) = self.subscription_mgr.subscribe(url, bootstrap).await?;
-        println!("[NODE] Subscribed successfully to {}", url);
-        Ok(receiver)
+        subscriptions.set_secret(url, secret);
+        self.subscribe(url, bootstrap).await
+    }
+
+    /// A ticket inviting other peers to a resource through this node. It
+    /// carries the resource's secret if it is private.
+    pub fn ticket(&self, url: &str) -> BraidTicket {
+        let ticket = BraidTicket::new(url, vec![self.endpoint.addr()]);
+        match self.state.subscriptions.secret(url) {
+            Some(secret) => ticket.with_secret(secret),
+            None => ticket,
+        }
+    }
+
+    /// Grant `peer` `right` on a resource in this node's ACL and return a
+    /// ticket for it recording the grant.
+    pub async fn share(&self, url: &str, peer: EndpointId, right: Right) -> Result<BraidTicket> {
+        if !self.state.acl.allows(Some(&peer), url, right) {
+            let acl = self.acl().allow(url, Principal::Peer(peer), right);
+            self.set_acl(&acl).await?;
+        }
+        Ok(self.ticket(url).with_right(right))
+    }
+
+    /// Join the resource a ticket points to: add its bootstrap addresses to
+    /// the endpoint's address book, subscribe (privately if it carries a secret) and fetch
+    /// the history the bootstrap peers have before returning. Peers that
+    /// can't be reached for catch-up are skipped; gossip still brings
+    /// their updates once they join.
+    #[tracing::instrument(
+        name = "redeem",
+        skip_all,
+        fields(node = %self.endpoint.id(), url = %ticket.url)
+    )]
+    pub async fn redeem(&self, ticket: &BraidTicket) -> Result<UpdateStream> {
+        ticket.validate()?;
+        for addr in &ticket.bootstrap {
+            self.address_book.add_endpoint_info(addr.clone());
+        }
+        let stream = match &ticket.secret {
+            Some(secret) => {
+                self.subscribe_private(&ticket.url, secret.clone(), ticket.peers())
+                    .await?
+            }
+            None => self.subscribe(&ticket.url, ticket.peers()).await?,
+        };
+        for peer in ticket.peers() {
+            match self.catch_up(&ticket.url, peer).await {
+                Ok(added) => tracing::debug!(peer = %peer, added, "caught up from ticket peer"),
+                Err(e) => tracing::warn!(peer = %peer, "catch-up failed: {}", e),
+            }
+        }
+        Ok(stream)
+    }
+
+    /// Leave a resource's gossip topic: stop applying gossip for it, drop
+    /// its event hooks and quit the topic. Stored history and `updates`
+    /// streams are kept. Returns `false` if the resource wasn't subscribed.
+    #[tracing::instrument(
+        name = "unsubscribe",
+        skip_all,
+        fields(node = %self.endpoint.id(), url = %SubscriptionManager::normalize_url(url))
+    )]
+    pub async fn unsubscribe(&self, url: &str) -> bool {
+        self.topics.leave(url).await
+    }
+
+    /// URLs of all resources whose gossip topic this node has joined.
+    pub async fn active_topics(&self) -> Vec<String> {
+        self.state.subscriptions.active_topics().await
+    }
+
+    /// Direct gossip neighbors on a subscribed resource's topic.
+    pub async fn neighbors(&self, url: &str) -> Result<Vec<EndpointId>> {
+        self.state.subscriptions.neighbors(url).await
+    }
+
+    /// Run `hook` for neighbor up/down and lag events on a resource's
+    /// topic, until the resource is unsubscribed.
+    pub fn on_topic_event(&self, url: &str, hook: TopicHook) {
+        self.state.subscriptions.on_event(url, hook);
+    }
+
+    /// Stream updates accepted for a resource without joining its gossip
+    /// topic (e.g. to watch local and HTTP/3 writes only).
+    pub fn updates(&self, url: &str) -> UpdateStream {
+        self.state
+            .feed
+            .subscribe(&SubscriptionManager::normalize_url(url))
     }
 
     /// PUT a Braid Update to a resource. Stores it locally and broadcasts
-    /// to all gossip subscribers.
-    pub async fn put(&self, url: &str, update: Update) -> anyhow::Result<()> {
+    /// to all gossip subscribers if this node has joined the resource's
+    /// topic. The update is signed with this node's key and stored with
+    /// this node as its author.
+    ///
+    /// An update without `parents` is taken to build on the resource's
+    /// current frontier.
+    #[tracing::instrument(
+        name = "put",
+        skip_all,
+        fields(
+            node = %self.endpoint.id(),
+            url = %SubscriptionManager::normalize_url(url),
+            version = %version_string(&update),
+        )
+    )]
+    pub async fn put(&self, url: &str, mut update: Update) -> Result<()> {
+        if self.shutdown.is_cancelled() {
+            return Err(BraidIrohError::ShuttingDown);
+        }
         // Normalize the URL for consistent storage (using same logic as SubscriptionManager)
         let normalized = crate::subscription::SubscriptionManager::normalize_url(url);
-        
-        // Debug logging for Braid format
-        println!("\nOUTGOING BRAID PUT:");
-        println!("PUT {} HTTP/3", normalized);
-        println!("Version: {:?}", update.version);
-        if !update.parents.is_empty() {
-            println!("Parents: {:?}", update.parents);
-        }
+        self.state.default_parents(&normalized, &mut update);
+
+        tracing::debug!(
+            parents = %codec::format_versions(&update.parents),
+            body_len = update.body.as_ref().map(|b| b.len()).unwrap_or(0),
+            patches = update.patches.as_ref().map(|p| p.len()).unwrap_or(0),
+            "outgoing put"
+        );
         if let Some(body) = &update.body {
-            println!("Content-Length: {}", body.len());
-            println!();
-            println!("{}", String::from_utf8_lossy(body));
+            self.state.redaction.trace_body("outgoing put body", body);
         }
-        println!("----------------------------------------\n");
 
-        self.resources
-            .write()
-            .await
-            .entry(normalized.clone())
-            .or_insert_with(Vec::new)
-            .push(update.clone());
-        self.subscription_mgr.broadcast(&normalized, &update).await?;
+        assign_version(&mut update);
+        self.state
+            .authorize_write(Some(&self.node_id()), &normalized, &update)?;
+        let signed = self.state.subscriptions.sign(&normalized, update)?;
+        if self.state.ingest_attributed(
+            &normalized,
+            signed.update.clone(),
+            Some(signed.attribution.clone()),
+        )? {
+            // Only resources we're subscribed to have a topic to publish on.
+            match self.state.subscriptions.broadcast_signed(&normalized, &signed).await {
+                Ok(()) | Err(BraidIrohError::TopicNotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
         Ok(())
     }
 
-    /// Store an update locally without broadcasting (for received gossip).
-    pub async fn store_update(&self, url: &str, update: Update) {
-        self.resources
-            .write()
-            .await
-            .entry(url.to_string())
-            .or_insert_with(Vec::new)
-            .push(update);
+    /// Store an update locally without broadcasting.
+    ///
+    /// Gossip received on subscribed topics is applied automatically; this
+    /// is for updates obtained some other way. Returns `false` if the
+    /// version was already stored.
+    pub async fn store_update(&self, url: &str, update: Update) -> Result<bool> {
+        let normalized = SubscriptionManager::normalize_url(url);
+        self.state.ingest(&normalized, update)
     }
 
-    /// GET the latest state of a resource from local storage.
+    /// GET the current state of a resource from local storage.
+    ///
+    /// When the resource has concurrent versions they are combined with the
+    /// resource's `Merge-Type`, and the returned update lists every head in
+    /// its `version`.
     #[allow(dead_code)]
     pub async fn get(&self, url: &str) -> Option<Update> {
-        self.resources
-            .read()
-            .await
-            .get(url)
-            .and_then(|h| h.last().cloned())
+        self.state
+            .resolve(&SubscriptionManager::normalize_url(url))
     }
 
     /// GET a specific version of a resource.
     pub async fn get_version(&self, url: &str, version_id: &str) -> Option<Update> {
-        self.resources.read().await.get(url).and_then(|history| {
-            history
-                .iter()
-                .find(|u| u.version.iter().any(|v| v.to_string().contains(version_id)))
-                .cloned()
-        })
+        self.state
+            .store
+            .get_version(&SubscriptionManager::normalize_url(url), version_id)
+    }
+
+    /// The verified author of a version, if it was signed.
+    pub async fn get_author(&self, url: &str, version_id: &str) -> Option<Attribution> {
+        self.state
+            .store
+            .attribution(&SubscriptionManager::normalize_url(url), version_id)
     }
 
-    /// GET all version IDs for a resource (latest last).
+    /// GET all version IDs for a resource, parents before children.
+    ///
+    /// Concurrent versions are ordered deterministically, so every peer
+    /// holding the same versions returns the same list.
     pub async fn get_history(&self, url: &str) -> Vec<String> {
-        if let Some(history) = self.resources.read().await.get(url) {
-            history
-                .iter()
-                .map(|u| {
-                    u.version
-                        .iter()
-                        .map(|v| v.to_string())
-                        .collect::<Vec<_>>()
-                        .join(",")
-                })
-                .collect()
-        } else {
-            Vec::new()
-        }
+        self.state
+            .store
+            .history(&SubscriptionManager::normalize_url(url))
+            .iter()
+            .map(version_string)
+            .collect()
+    }
+
+    /// The current heads of a resource's version graph. More than one entry
+    /// means there are concurrent, not-yet-merged versions.
+    pub async fn frontier(&self, url: &str) -> Vec<String> {
+        self.state
+            .store
+            .frontier(&SubscriptionManager::normalize_url(url))
+    }
+
+    /// List the URLs of all resources held in local storage.
+    pub fn resources(&self) -> Vec<String> {
+        self.state.store.list()
+    }
+
+    /// Directory-style listing of local resources under `prefix` (e.g.
+    /// `/docs/`). Deeper levels are collapsed into `<prefix>/<name>/`
+    /// entries unless `recursive` is set.
+    pub fn list(&self, prefix: &str, recursive: bool) -> Vec<String> {
+        list_directory(self.state.store.as_ref(), prefix, recursive)
+    }
+
+    /// The access rules in force.
+    pub fn acl(&self) -> Acl {
+        self.state.acl.current()
+    }
+
+    /// Replace the access rules by writing a new version of the ACL
+    /// resource at `ACL_URL`. Peers subscribed to it pick up the change.
+    pub async fn set_acl(&self, acl: &Acl) -> Result<()> {
+        let mut update = Update::snapshot(
+            Version::String(uuid::Uuid::new_v4().to_string()),
+            Bytes::from(serde_json::to_vec(acl)?),
+        );
+        update.content_type = Some("application/json".to_string());
+        self.put(ACL_URL, update).await
+    }
+
+    /// Register a merge type under its `Merge-Type` name, replacing any
+    /// existing one. `lww` and `text` are available by default.
+    pub fn register_merge_type(&self, merge_type: Arc<dyn MergeType>) {
+        self.state.merges.register(merge_type);
+    }
+
+    /// The merge types available on this node.
+    pub fn merge_types(&self) -> &MergeRegistry {
+        &self.state.merges
+    }
+
+    /// Counters, gauges and histograms for this node's gossip, storage,
+    /// HTTP/3 and proxy traffic.
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.state.metrics
+    }
+
+    /// Access the resource store (for advanced usage).
+    pub fn store(&self) -> &Arc<dyn ResourceStore> {
+        &self.state.store
+    }
+
+    /// The state the node's routes run on, for request-level tests.
+    #[cfg(test)]
+    pub(crate) fn app_state(&self) -> &BraidAppState {
+        &self.state
     }
 
     /// Shut down the node gracefully.
-    #[allow(dead_code)]
-    pub async fn shutdown(self) -> anyhow::Result<()> {
-        self.router.shutdown().await?;
-        Ok(())
+    ///
+    /// Stops the proxy (letting in-flight requests finish), stops applying
+    /// gossip, leaves every topic once pending broadcasts are sent, flushes
+    /// storage and closes the endpoint. Later `put`s and `subscribe`s fail
+    /// with `BraidIrohError::ShuttingDown`. Safe to call more than once and
+    /// from several holders of an `Arc<BraidIrohNode>`; every caller waits
+    /// for the same teardown.
+    pub async fn shutdown(&self) -> Result<()> {
+        self.shutdown.cancel();
+        self.teardown.run().await
+    }
+
+    /// A token that triggers `shutdown` when cancelled, and can be awaited
+    /// (`cancelled()`) to learn when the node starts shutting down.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
     }
 
     /// Access the subscription manager (for advanced usage).
     #[allow(dead_code)]
     pub fn subscriptions(&self) -> &Arc<SubscriptionManager> {
-        &self.subscription_mgr
+        &self.state.subscriptions
     }
 
     /// Access the iroh endpoint (for advanced usage).
@@ -268,12 +749,278 @@ impl BraidIrohNode {
         &self.endpoint
     }
 
+    /// Fetch any versions of `url` that `peer` has and we don't.
+    /// Returns the number of updates added.
+    pub async fn catch_up(&self, url: &str, peer: EndpointId) -> Result<usize> {
+        let normalized = SubscriptionManager::normalize_url(url);
+        sync::catch_up(&self.topics.client, &self.state, peer, &normalized).await
+    }
+
     /// Join additional peers to an existing gossip topic.
-    pub async fn join_peers(&self, url: &str, peers: Vec<EndpointId>) -> anyhow::Result<()> {
-        println!("[NODE] Joining {} peers to topic {}", peers.len(), url);
-        for peer in &peers {
-            println!("[NODE]   joining: {}", peer);
+    #[tracing::instrument(
+        name = "join_peers",
+        skip_all,
+        fields(
+            node = %self.endpoint.id(),
+            url = %SubscriptionManager::normalize_url(url),
+            topic = %self.state.subscriptions.topic_id(url),
+        )
+    )]
+    pub async fn join_peers(&self, url: &str, peers: Vec<EndpointId>) -> Result<()> {
+        tracing::debug!(peers = ?peers, "joining peers");
+        self.state.subscriptions.join_peers(url, peers).await
+    }
+}
+
+impl Drop for BraidIrohNode {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// Apply every update received on a gossip topic until the topic closes.
+///
+/// Whenever a new neighbor shows up, or an update arrives whose parents we
+/// don't have, the missing history is fetched from that peer over HTTP/3,
+/// unless a catch-up with it is already running.
+async fn ingest_gossip(
+    state: BraidAppState,
+    client: IrohH3Client,
+    catch_ups: CatchUpSlots,
+    url: String,
+    mut receiver: GossipReceiver,
+) {
+    while let Some(event) = receiver.next().await {
+        match event {
+            Ok(Event::Received(msg)) => match decode_gossip(&state, &url, &msg.content) {
+                Ok(signed) => {
+                    state.metrics.gossip_updates_received.inc();
+                    state.metrics.gossip_bytes_received.add(msg.content.len() as u64);
+                    // The author is whoever signed it; `delivered_from` may
+                    // just be a neighbor relaying it.
+                    let (update, attribution) = match signed.verify(&url) {
+                        Ok(verified) => verified,
+                        Err(e) => {
+                            state.metrics.gossip_bad_signatures.inc();
+                            tracing::warn!(peer = %msg.delivered_from, "dropping gossip update: {}", e);
+                            continue;
+                        }
+                    };
+                    if let Err(e) = state.authorize_write(Some(&attribution.author), &url, &update) {
+                        tracing::warn!(author = %attribution.author, "dropping gossip update: {}", e);
+                        continue;
+                    }
+                    tracing::debug!(
+                        peer = %msg.delivered_from,
+                        author = %attribution.author,
+                        version = %version_string(&update),
+                        "received gossip update"
+                    );
+                    if let Some(body) = &update.body {
+                        state.redaction.trace_body("gossip update body", body);
+                    }
+                    if let Err(e) = state.ingest_attributed(&url, update, Some(attribution)) {
+                        tracing::error!("failed to store gossip update: {}", e);
+                    }
+                    let has_gaps = state
+                        .store
+                        .graph(&url)
+                        .map(|g| !g.missing().is_empty())
+                        .unwrap_or(false);
+                    if has_gaps {
+                        spawn_catch_up(&state, &client, &catch_ups, msg.delivered_from, &url);
+                    }
+                }
+                Err(e) => {
+                    state.metrics.gossip_decode_errors.inc();
+                    tracing::warn!(peer = %msg.delivered_from, "undecodable gossip message: {}", e);
+                }
+            },
+            Ok(Event::NeighborUp(peer)) => {
+                tracing::debug!(peer = %peer, "neighbor up");
+                state
+                    .subscriptions
+                    .record_event(&url, TopicEvent::NeighborUp(peer))
+                    .await;
+                spawn_catch_up(&state, &client, &catch_ups, peer, &url);
+            }
+            Ok(Event::NeighborDown(peer)) => {
+                tracing::debug!(peer = %peer, "neighbor down");
+                state
+                    .subscriptions
+                    .record_event(&url, TopicEvent::NeighborDown(peer))
+                    .await;
+            }
+            Ok(Event::Lagged) => {
+                // Messages were dropped; whatever they carried can be
+                // fetched from the neighbors we still have.
+                state.subscriptions.record_event(&url, TopicEvent::Lagged).await;
+                for peer in state.subscriptions.neighbors(&url).await.unwrap_or_default() {
+                    spawn_catch_up(&state, &client, &catch_ups, peer, &url);
+                }
+            }
+            Err(e) => {
+                tracing::warn!("gossip receiver closed: {}", e);
+                break;
+            }
         }
-        self.subscription_mgr.join_peers(url, peers).await
+    }
+}
+
+/// Decrypt (for a private resource) and decode a gossip payload.
+fn decode_gossip(state: &BraidAppState, url: &str, payload: &[u8]) -> Result<SignedUpdate> {
+    let payload = state.subscriptions.open_payload(url, payload)?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+/// Run a catch-up against `peer` in the background, unless one is running.
+fn spawn_catch_up(
+    state: &BraidAppState,
+    client: &IrohH3Client,
+    catch_ups: &CatchUpSlots,
+    peer: EndpointId,
+    url: &str,
+) {
+    let Some(slot) = catch_ups.claim(url, peer) else {
+        tracing::debug!(peer = %peer, "catch-up already running");
+        return;
+    };
+    let state = state.clone();
+    let client = client.clone();
+    let url = url.to_string();
+    let span = tracing::debug_span!("catch_up", peer = %peer);
+    tokio::spawn(
+        async move {
+            if let Err(e) = sync::catch_up(&client, &state, peer, &url).await {
+                tracing::warn!("catch-up failed: {}", e);
+            }
+            drop(slot);
+        }
+        .instrument(span),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_node;
+
+    #[tokio::test]
+    async fn test_subscribe_private_refuses_a_public_subscription() {
+        let node = test_node(&DiscoveryConfig::mock(), Acl::open()).await;
+        let secret = ResourceSecret::generate();
+
+        let _updates = node.subscribe("/doc", vec![]).await.unwrap();
+        assert!(matches!(
+            node.subscribe_private("/doc", secret.clone(), vec![]).await,
+            Err(BraidIrohError::Sealed(_))
+        ));
+        assert!(node.subscriptions().secret("/doc").is_none());
+
+        let _first = node.subscribe_private("/other", secret.clone(), vec![]).await.unwrap();
+        let _again = node.subscribe_private("/other", secret, vec![]).await.unwrap();
+        assert!(matches!(
+            node.subscribe_private("/other", ResourceSecret::generate(), vec![]).await,
+            Err(BraidIrohError::Sealed(_))
+        ));
+
+        node.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_the_proxy_and_leaves_topics() {
+        use crate::test_util::wait_for;
+        use tokio::net::TcpStream;
+
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let node = BraidIrohNode::builder()
+            .discovery(DiscoveryConfig::mock())
+            .relay_mode(RelayMode::Disabled)
+            .proxy(addr)
+            .spawn()
+            .await
+            .unwrap();
+        let _updates = node.subscribe("/doc", vec![]).await.unwrap();
+        wait_for("the proxy to listen", || async { TcpStream::connect(addr).await.is_ok() }).await;
+
+        node.shutdown().await.unwrap();
+        assert!(TcpStream::connect(addr).await.is_err());
+        assert!(node.subscriptions().active_topics().await.is_empty());
+        let update = Update::snapshot(Version::String("1".into()), Bytes::from("hi"));
+        assert!(matches!(
+            node.put("/doc", update).await,
+            Err(BraidIrohError::ShuttingDown)
+        ));
+        assert!(matches!(
+            node.subscribe("/other", vec![]).await,
+            Err(BraidIrohError::ShuttingDown)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_gossip_reaches_subscribers_once() {
+        use crate::test_util::wait_for;
+        use std::time::Duration;
+
+        let discovery = DiscoveryConfig::mock();
+        let alice = test_node(&discovery, Acl::open()).await;
+        let bob = test_node(&discovery, Acl::open()).await;
+        let _alice_updates = alice.subscribe("/doc", vec![]).await.unwrap();
+        let mut bob_updates = bob.subscribe("/doc", vec![alice.node_id()]).await.unwrap();
+        wait_for("alice and bob to be neighbors", || async {
+            !alice.subscriptions().neighbors("/doc").await.unwrap().is_empty()
+        })
+        .await;
+
+        let update = Update::snapshot(Version::String("1".into()), Bytes::from("hi"));
+        alice.put("/doc", update).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(10), bob_updates.next())
+            .await
+            .expect("bob gets the update")
+            .unwrap();
+        assert_eq!(received.body, Some(Bytes::from("hi")));
+        assert_eq!(bob.get_history("/doc").await, vec!["1"]);
+
+        // The same signed update again, in different bytes so gossip
+        // doesn't drop it as a message it has already seen.
+        let signed = alice
+            .subscriptions()
+            .sign("/doc", alice.get("/doc").await.unwrap())
+            .unwrap();
+        let again = Bytes::from(serde_json::to_vec_pretty(&signed).unwrap());
+        alice.subscriptions().broadcast_raw("/doc", again).await.unwrap();
+        wait_for("bob to see the duplicate", || async {
+            bob.metrics().updates_duplicate.get() == 1
+        })
+        .await;
+        assert_eq!(bob.get_history("/doc").await, vec!["1"]);
+        assert_eq!(bob.metrics().updates_stored.get(), 1);
+
+        alice.shutdown().await.unwrap();
+        bob.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redeem_reaches_ticket_peers_with_real_discovery() {
+        let spawn = || {
+            BraidIrohNode::builder()
+                .discovery(DiscoveryConfig::Real)
+                .relay_mode(RelayMode::Disabled)
+                .spawn()
+        };
+        let issuer = spawn().await.unwrap();
+        let update = Update::snapshot(Version::String("1".into()), Bytes::from("hi"));
+        issuer.put("/doc", update).await.unwrap();
+        let _issuer_updates = issuer.subscribe("/doc", vec![]).await.unwrap();
+
+        let node = spawn().await.unwrap();
+        let _updates = node.redeem(&issuer.ticket("/doc")).await.unwrap();
+        assert!(node.get_version("/doc", "1").await.is_some());
+
+        node.shutdown().await.unwrap();
+        issuer.shutdown().await.unwrap();
     }
 }
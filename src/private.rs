@@ -0,0 +1,200 @@
+//! Private topics.
+//!
+//! A public resource's gossip topic is `blake3(url)`, so anyone who can
+//! guess a URL can join its topic and read every update. A private resource
+//! has a 32-byte `ResourceSecret` shared by its participants, which
+//!
+//! - salts the topic ID (`blake3` keyed with a key derived from the
+//!   secret), so the topic can't be found from the URL alone, and
+//! - encrypts and authenticates every gossip payload with
+//!   XChaCha20-Poly1305 under another key derived from the secret, bound to
+//!   the resource URL.
+//!
+//! The secret is the capability: hand it to a peer to let them find and
+//! read the resource's updates. Peers without it see neither.
+
+use bytes::Bytes;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use iroh_gossip::proto::TopicId;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{BraidIrohError, Result};
+use crate::signing::{hex_decode, hex_encode};
+use crate::subscription::SubscriptionManager;
+
+/// Marks a sealed gossip payload.
+const SEALED_MAGIC: &[u8; 4] = b"BRX1";
+const NONCE_LEN: usize = 24;
+
+const TOPIC_KEY_CONTEXT: &str = "braid-iroh 2025 private topic id";
+const PAYLOAD_KEY_CONTEXT: &str = "braid-iroh 2025 private topic payload";
+
+/// The shared secret of a private resource. Serialized as hex, so it can
+/// travel in a ticket.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ResourceSecret([u8; 32]);
+
+impl ResourceSecret {
+    /// A fresh random secret.
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    /// A secret from its raw bytes, e.g. one kept by the application.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// The raw secret. Anyone holding these bytes can read the resource.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// The topic ID of `url` for holders of this secret.
+    pub fn topic_for_url(&self, url: &str) -> TopicId {
+        let key = blake3::derive_key(TOPIC_KEY_CONTEXT, &self.0);
+        let normalized = SubscriptionManager::normalize_url(url);
+        TopicId::from_bytes(*blake3::keyed_hash(&key, normalized.as_bytes()).as_bytes())
+    }
+
+    /// Encrypt a gossip payload for `url`.
+    pub fn seal(&self, url: &str, plaintext: &[u8]) -> Result<Bytes> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let aad = SubscriptionManager::normalize_url(url);
+        let ciphertext = self
+            .cipher()
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| BraidIrohError::Sealed(format!("failed to encrypt for {}", aad)))?;
+
+        let mut sealed = Vec::with_capacity(SEALED_MAGIC.len() + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(SEALED_MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(Bytes::from(sealed))
+    }
+
+    /// Decrypt and authenticate a gossip payload sealed for `url`.
+    pub fn open(&self, url: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+        let aad = SubscriptionManager::normalize_url(url);
+        let rest = sealed
+            .strip_prefix(SEALED_MAGIC.as_slice())
+            .filter(|rest| rest.len() >= NONCE_LEN)
+            .ok_or_else(|| {
+                BraidIrohError::Sealed(format!("unsealed payload on private topic {}", aad))
+            })?;
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        self.cipher()
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| BraidIrohError::Sealed(format!("payload for {} failed to decrypt", aad)))
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        let key = blake3::derive_key(PAYLOAD_KEY_CONTEXT, &self.0);
+        XChaCha20Poly1305::new(&key.into())
+    }
+}
+
+/// Never print the secret itself.
+impl fmt::Debug for ResourceSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ResourceSecret(..)")
+    }
+}
+
+/// Hex, for sharing the capability out of band.
+impl fmt::Display for ResourceSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex_encode(&self.0))
+    }
+}
+
+impl FromStr for ResourceSecret {
+    type Err = BraidIrohError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        hex_decode(s)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(Self)
+            .ok_or_else(|| {
+                BraidIrohError::Sealed("resource secret must be 64 hex digits".to_string())
+            })
+    }
+}
+
+impl From<ResourceSecret> for String {
+    fn from(secret: ResourceSecret) -> Self {
+        secret.to_string()
+    }
+}
+
+impl TryFrom<String> for ResourceSecret {
+    type Error = BraidIrohError;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let secret = ResourceSecret::generate();
+        let sealed = secret.seal("/doc", b"hello").unwrap();
+        assert!(!sealed.windows(5).any(|w| w == b"hello"));
+        assert_eq!(secret.open("doc/", &sealed).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_open_rejects_wrong_secret_url_or_tampering() {
+        let secret = ResourceSecret::generate();
+        let sealed = secret.seal("/doc", b"hello").unwrap();
+
+        assert!(ResourceSecret::generate().open("/doc", &sealed).is_err());
+        assert!(secret.open("/other", &sealed).is_err());
+
+        let mut tampered = sealed.to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(secret.open("/doc", &tampered).is_err());
+        assert!(secret.open("/doc", b"{\"version\":[]}").is_err());
+    }
+
+    #[test]
+    fn test_private_topic_differs_from_public() {
+        let a = ResourceSecret::from_bytes([1; 32]);
+        let b = ResourceSecret::from_bytes([2; 32]);
+        assert_ne!(
+            a.topic_for_url("/doc"),
+            SubscriptionManager::topic_for_url("/doc")
+        );
+        assert_ne!(a.topic_for_url("/doc"), b.topic_for_url("/doc"));
+        assert_eq!(a.topic_for_url("/doc"), a.topic_for_url("doc/"));
+    }
+
+    #[test]
+    fn test_secret_hex_roundtrip() {
+        let secret = ResourceSecret::generate();
+        let parsed: ResourceSecret = secret.to_string().parse().unwrap();
+        assert_eq!(parsed, secret);
+        assert!("abc".parse::<ResourceSecret>().is_err());
+        assert_eq!(format!("{:?}", secret), "ResourceSecret(..)");
+    }
+}
@@ -4,20 +4,38 @@
 //! with Version/Parents headers) are served over HTTP/3 on iroh QUIC
 //! connections. This is the bridge between the P2P transport and the
 //! existing braid_http_rs server middleware.
+//!
+//! Every request is checked against the node's ACL for the peer at the
+//! other end of the QUIC connection.
 
 use axum::{
-    extract::{Query, State},
-    response::IntoResponse,
-    routing::{get, put},
+    body::Body,
+    extract::{FromRequestParts, Query, Request, State},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
     Router,
 };
 use braid_http_rs::{Update, Version};
 use bytes::Bytes;
+use futures::StreamExt;
 use http::StatusCode;
-use iroh_h3_axum::IrohAxum;
+use iroh::EndpointId;
+use iroh_h3_axum::{IrohAxum, RemoteId};
 use std::collections::HashMap;
+use std::convert::Infallible;
 use std::sync::Arc;
+use std::time::Instant;
 
+use crate::acl::{Acl, AclManager, Right, ACL_URL};
+use crate::codec;
+use crate::error::{BraidIrohError, Result};
+use crate::feed::UpdateFeed;
+use crate::merge::MergeRegistry;
+use crate::metrics::Metrics;
+use crate::redact::RedactionPolicy;
+use crate::signing::{Attribution, AUTHOR_HEADER, SIGNATURE_HEADER};
+use crate::storage::{list_directory, version_string, ResourceStore};
 use crate::subscription::SubscriptionManager;
 
 /// Shared state accessible from Axum route handlers.
@@ -25,172 +43,487 @@ use crate::subscription::SubscriptionManager;
 pub struct BraidAppState {
     /// Subscription manager for gossip-backed pub/sub.
     pub subscriptions: Arc<SubscriptionManager>,
-    /// In-memory resource store: URL → List of Updates (History).
-    pub resources: Arc<tokio::sync::RwLock<std::collections::HashMap<String, Vec<Update>>>>,
+    /// Resource store shared with the owning node: URL → history.
+    pub store: Arc<dyn ResourceStore>,
+    /// Live update streams, fed by every accepted update.
+    pub feed: UpdateFeed,
+    /// Merge types used to combine concurrent versions.
+    pub merges: MergeRegistry,
+    /// How resource bodies appear in trace logs.
+    pub redaction: RedactionPolicy,
+    /// Metrics shared with the owning node.
+    pub metrics: Arc<Metrics>,
+    /// Who may read, write and administer which resources.
+    pub acl: AclManager,
+}
+
+impl BraidAppState {
+    /// Accept an update into the store and publish it to live streams.
+    ///
+    /// This is the single path every update goes through, whether it came
+    /// from a local `put`, an HTTP/3 PUT or gossip. Updates whose version is
+    /// already stored are ignored; returns `false` in that case.
+    pub fn ingest(&self, url: &str, update: Update) -> Result<bool> {
+        self.ingest_attributed(url, update, None)
+    }
+
+    /// `ingest`, recording the update's verified author with the version.
+    pub fn ingest_attributed(
+        &self,
+        url: &str,
+        mut update: Update,
+        attribution: Option<Attribution>,
+    ) -> Result<bool> {
+        assign_version(&mut update);
+        // Storing is the duplicate check: of two deliveries of the same
+        // version racing here (gossip and catch-up, say), exactly one is
+        // stored and published.
+        match self.store.append_attributed(url, update.clone(), attribution) {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::debug!(
+                    url = %url,
+                    version = %version_string(&update),
+                    "ignoring duplicate update"
+                );
+                self.metrics.updates_duplicate.inc();
+                return Ok(false);
+            }
+            Err(e) => {
+                self.metrics.storage_errors.inc();
+                return Err(e);
+            }
+        }
+        self.metrics.updates_stored.inc();
+        if url == ACL_URL {
+            self.reload_acl();
+        }
+        self.feed.publish(url, &update);
+        Ok(true)
+    }
+
+    /// Check that `peer` (`None` for anonymous) may write `update` to
+    /// `url`. A write to the ACL resource must be a full JSON `Acl`, and
+    /// needs `admin` on every rule it adds or removes rather than `write`.
+    /// As for reads, a private resource needs a write grant naming `peer`.
+    pub fn authorize_write(
+        &self,
+        peer: Option<&EndpointId>,
+        url: &str,
+        update: &Update,
+    ) -> Result<()> {
+        let result = if url == ACL_URL {
+            let proposed: Acl = serde_json::from_slice(update.body.as_deref().unwrap_or_default())?;
+            self.acl.check_change(peer, &proposed)
+        } else if self.subscriptions.secret(url).is_some() {
+            self.acl.check_granted(peer, url, Right::Write)
+        } else {
+            self.acl.check(peer, url, Right::Write)
+        };
+        if matches!(result, Err(BraidIrohError::Forbidden(_))) {
+            self.metrics.acl_denials.inc();
+        }
+        result
+    }
+
+    /// Check that `peer` may read `url`. A private resource (one with a
+    /// `ResourceSecret`) needs a read grant naming `peer`; `*` rules don't
+    /// open it up.
+    pub fn authorize_read(&self, peer: Option<&EndpointId>, url: &str) -> Result<()> {
+        let result = if self.subscriptions.secret(url).is_some() {
+            self.acl.check_granted(peer, url, Right::Read)
+        } else {
+            self.acl.check(peer, url, Right::Read)
+        };
+        if result.is_err() {
+            self.metrics.acl_denials.inc();
+        }
+        result
+    }
+
+    /// `authorize_read` as a plain answer, without counting a denial.
+    pub fn can_read(&self, peer: Option<&EndpointId>, url: &str) -> bool {
+        if self.subscriptions.secret(url).is_some() {
+            self.acl.grants(peer, url, Right::Read)
+        } else {
+            self.acl.allows(peer, url, Right::Read)
+        }
+    }
+
+    /// Put the stored ACL resource (if any) into force.
+    pub fn reload_acl(&self) {
+        let Some(current) = self.resolve(ACL_URL) else {
+            return;
+        };
+        match serde_json::from_slice::<Acl>(current.body.as_deref().unwrap_or_default()) {
+            Ok(acl) => {
+                tracing::info!(rules = acl.rules.len(), "ACL updated");
+                self.acl.replace(acl);
+            }
+            Err(e) => tracing::error!("stored ACL is not valid, keeping the previous one: {}", e),
+        }
+    }
+
+    /// The current state of a resource: its version graph merged with the
+    /// resource's `Merge-Type`.
+    pub fn resolve(&self, url: &str) -> Option<Update> {
+        self.merges.resolve(url, &self.store.graph(url)?)
+    }
+
+    /// Base a locally authored update on the resource's current heads when
+    /// the writer didn't name any parents.
+    pub fn default_parents(&self, url: &str, update: &mut Update) {
+        if update.parents.is_empty() {
+            update.parents = self
+                .store
+                .frontier(url)
+                .into_iter()
+                .map(Version::String)
+                .collect();
+        }
+    }
+}
+
+/// Give an update without a version a fresh random one. Done before an
+/// update is signed, since the signature covers the version.
+pub fn assign_version(update: &mut Update) {
+    if update.version.is_empty() {
+        update.version = vec![Version::String(uuid::Uuid::new_v4().to_string())];
+    }
 }
 
 /// Build the Axum router with Braid-HTTP routes, then wrap it in
 /// `IrohAxum` so it can be mounted on an iroh endpoint.
 ///
+/// Resource paths may be nested (`/docs/team/notes`); they are normalized
+/// the same way as storage keys and gossip topics.
+///
 /// Routes:
-/// - `GET /:resource`  → returns the latest snapshot
-/// - `PUT /:resource`  → accepts a new update, broadcasts via gossip
+/// - `GET /*resource`  → returns the latest version, Braid metadata in headers
+/// - `GET /*resource` with `Subscribe: true` → 209, streams every update
+/// - `GET /*prefix/` (and `GET /`) → JSON listing of resources under the prefix
+/// - `PUT /*resource`  → accepts a new update, broadcasts via gossip if subscribed
+///
+/// Every request is counted in `BraidAppState::metrics`.
 pub fn build_protocol_handler(state: BraidAppState) -> IrohAxum {
-    let router = Router::new()
-        .route("/{resource}", get(handle_get))
-        .route("/{resource}", put(handle_put))
-        .with_state(state);
+    IrohAxum::new(router(state))
+}
+
+/// The Braid-HTTP routes on their own, for serving them outside iroh (the
+/// TCP proxy serves the local node through this). Requests are checked
+/// against the ACL for the `RemoteId` in their extensions, if any.
+pub fn router(state: BraidAppState) -> Router {
+    Router::new()
+        .route("/", get(handle_list_root))
+        .route("/{*resource}", get(handle_get).put(handle_put))
+        .layer(middleware::from_fn_with_state(state.clone(), record_request))
+        .with_state(state)
+}
+
+/// The endpoint at the other end of the QUIC connection.
+///
+/// `IrohAxum` records the connection's authenticated remote ID as a
+/// `RemoteId` extension on every request it accepts; that is the only
+/// identity the handlers trust. Requests served any other way carry none
+/// and are anonymous, unless whoever serves them inserts a `RemoteId`
+/// (see `proxy` for the principal of proxy-local requests).
+struct RemotePeer(Option<EndpointId>);
 
-    IrohAxum::new(router)
+impl<S: Sync> FromRequestParts<S> for RemotePeer {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        _state: &S,
+    ) -> std::result::Result<Self, Infallible> {
+        Ok(RemotePeer(parts.extensions.get::<RemoteId>().map(|RemoteId(id)| *id)))
+    }
+}
+
+/// Count each request by method and status and time it until the
+/// response headers are ready (a subscription's body may stay open long
+/// after that).
+async fn record_request(State(state): State<BraidAppState>, req: Request, next: Next) -> Response {
+    let method = req.method().clone();
+    let started = Instant::now();
+    let response = next.run(req).await;
+    state.metrics.http_request_duration.observe(started.elapsed());
+    state
+        .metrics
+        .http_requests
+        .inc(&[method.as_str(), response.status().as_str()]);
+    response
 }
 
 use http::HeaderMap;
 
+/// Build a 209 response whose body stays open for the lifetime of the
+/// subscription. If the client falls too far behind for the feed to keep
+/// up, the body ends and the client is expected to subscribe again.
+///
+/// The body starts with the current version (if any) and then emits every
+/// update accepted for `url` afterwards — from PUTs, gossip, or local
+/// writes — in Braid multi-update framing. When the client goes away the
+/// body is dropped, which releases its slot on the update feed.
+fn subscription_response(state: &BraidAppState, url: &str) -> Response {
+    // Subscribe to the feed before reading the snapshot so nothing that
+    // lands in between is lost.
+    let live = state.feed.subscribe(url);
+    let initial = state.resolve(url);
+    let initial_version = initial.as_ref().map(version_string);
+
+    let frames = futures::stream::iter(initial)
+        .chain(live.filter(move |update| {
+            let duplicate = initial_version.as_deref() == Some(version_string(update).as_str());
+            futures::future::ready(!duplicate)
+        }))
+        .map(|update| Ok::<_, Infallible>(codec::encode_update(&update)));
+
+    Response::builder()
+        .status(StatusCode::from_u16(209).unwrap())
+        .header("subscribe", "true")
+        .header(http::header::CACHE_CONTROL, "no-cache, no-transform")
+        .body(Body::from_stream(frames))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+/// Directory listing of the resources under `prefix`, as a JSON array.
+/// Sub-directories appear as entries ending in `/`; `?recursive=true`
+/// lists every resource under the prefix instead.
+/// Only entries `peer` may read are listed, so private resources are
+/// hidden from peers without a grant.
+fn directory_response(
+    state: &BraidAppState,
+    peer: Option<&EndpointId>,
+    prefix: &str,
+    params: &HashMap<String, String>,
+) -> Response {
+    let recursive = params.get("recursive").map(|v| v == "true").unwrap_or(false);
+    let entries: Vec<String> = list_directory(state.store.as_ref(), prefix, recursive)
+        .into_iter()
+        .filter(|entry| state.can_read(peer, entry))
+        .collect();
+    (
+        StatusCode::OK,
+        [(http::header::CONTENT_TYPE, "application/json")],
+        serde_json::to_string(&entries).unwrap_or_default(),
+    )
+        .into_response()
+}
+
+/// GET `/` — listing of top-level resources.
+async fn handle_list_root(
+    State(state): State<BraidAppState>,
+    RemotePeer(peer): RemotePeer,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    directory_response(&state, peer.as_ref(), "/", &params)
+}
+
 /// GET handler — returns the current state of a resource.
-/// If the resource doesn't exist yet, returns 404.
+/// If the resource doesn't exist yet, returns 404. A path ending in `/`
+/// returns a directory listing instead.
+#[tracing::instrument(
+    name = "braid_get",
+    skip_all,
+    fields(url = %SubscriptionManager::normalize_url(&resource))
+)]
 async fn handle_get(
     State(state): State<BraidAppState>,
+    RemotePeer(peer): RemotePeer,
     axum::extract::Path(resource): axum::extract::Path<String>,
     Query(params): Query<HashMap<String, String>>,
     headers: HeaderMap,
 ) -> impl IntoResponse {
-    let url = format!("/{}", resource);
+    let url = SubscriptionManager::normalize_url(&resource);
+    let peer = peer.as_ref();
 
-    // Check for Subscribe header (Subscription 209 Demo)
+    if resource.ends_with('/') {
+        return directory_response(&state, peer, &url, &params);
+    }
+
+    if let Err(e) = state.authorize_read(peer, &url) {
+        return e.into_response();
+    }
+
+    // Subscribe: true → keep the response open and stream every update
     if let Some(val) = headers.get("subscribe") {
         if val == "true" {
-            println!("[PROTOCOL] Received Subscribe request for {}", url);
-
-            // Try to get current content to send as initial state (latest version)
-            let resources = state.resources.read().await;
-            println!("[PROTOCOL] Looking for resource in store, available keys: {:?}", resources.keys().collect::<Vec<_>>());
-            let initial_content = if let Some(history) = resources.get(&url) {
-                println!("[PROTOCOL] Found history with {} entries for {}", history.len(), url);
-                if let Some(latest) = history.last() {
-                    let content = serde_json::to_string(latest).unwrap_or_default();
-                    println!("[PROTOCOL] Sending initial content (len: {})", content.len());
-                    content
-                } else {
-                    println!("[PROTOCOL] History empty for {}", url);
-                    String::new()
-                }
-            } else {
-                // Empty initial state
-                println!("[PROTOCOL] Resource not found: {}", url);
-                String::new()
-            };
-
-            return (
-                StatusCode::from_u16(209).unwrap(),
-                [("subscribe", "true")],
-                initial_content,
-            )
-                .into_response();
-        }
-    }
-
-    let resources = state.resources.read().await;
-
-    if let Some(history) = resources.get(&url) {
-        // 1. Check for ?version=...
-        if let Some(ver) = params.get("version") {
-            // Basic implementation: check if version string contains the requested ID
-            // Optimally we'd use strict equality but Version enum makes it tricky strictly
-            if let Some(update) = history
-                .iter()
-                .find(|u| u.version.iter().any(|v| v.to_string() == *ver))
-            {
-                return (
-                    StatusCode::OK,
-                    serde_json::to_string(update).unwrap_or_default(),
-                )
-                    .into_response();
+            tracing::debug!("opening subscription");
+            return subscription_response(&state, &url);
+        }
+    }
+
+    // 1. Check for ?version=...
+    if let Some(ver) = params.get("version") {
+        return match state.store.get_version(&url, ver) {
+            Some(update) => {
+                let attribution = state.store.attribution(&url, ver);
+                braid_response(&update, attribution.as_ref(), &headers)
             }
-            return StatusCode::NOT_FOUND.into_response();
-        }
-
-        // 2. Check for ?history=true
-        if params.get("history").map(|v| v == "true").unwrap_or(false) {
-            // Return list of version strings
-            let versions: Vec<String> = history
-                .iter()
-                .map(|u| {
-                    u.version
-                        .iter()
-                        .map(|v| v.to_string())
-                        .collect::<Vec<_>>()
-                        .join(",")
-                })
-                .collect();
-            return (
-                StatusCode::OK,
-                serde_json::to_string(&versions).unwrap_or_default(),
-            )
-                .into_response();
+            None => BraidIrohError::VersionNotFound {
+                url,
+                version: ver.clone(),
+            }
+            .into_response(),
+        };
+    }
+
+    // 2. Check for ?history=true
+    if params.get("history").map(|v| v == "true").unwrap_or(false) {
+        let history = state.store.history(&url);
+        if history.is_empty() {
+            return BraidIrohError::NotFound(url).into_response();
         }
+        // Return list of version strings, parents before children
+        let versions: Vec<String> = history.iter().map(version_string).collect();
+        return (
+            StatusCode::OK,
+            [(http::header::CONTENT_TYPE, "application/json")],
+            serde_json::to_string(&versions).unwrap_or_default(),
+        )
+            .into_response();
+    }
 
-        // 3. Default: Return latest
-        if let Some(latest) = history.last() {
-            return (
-                StatusCode::OK,
-                serde_json::to_string(latest).unwrap_or_default(),
-            )
-                .into_response();
+    // 3. Default: Return the current (merged) state
+    // (a merge of several heads has no single author)
+    match state.resolve(&url) {
+        Some(latest) => {
+            let attribution = state.store.attribution(&url, &version_string(&latest));
+            braid_response(&latest, attribution.as_ref(), &headers)
         }
+        None => BraidIrohError::NotFound(url).into_response(),
+    }
+}
+
+/// Render an update as a Braid GET response.
+///
+/// The Braid metadata goes in `Version`, `Parents`, `Merge-Type` and
+/// `Content-Type` headers and the resource itself is the body — or, for a
+/// patch update, a `Patches: N` body. Clients that send
+/// `Accept: application/braid+json` get the whole `Update` serialized as
+/// JSON instead. Either way a signed version carries `Braid-Author` and
+/// `Braid-Signature` headers.
+fn braid_response(
+    update: &Update,
+    attribution: Option<&Attribution>,
+    request_headers: &HeaderMap,
+) -> Response {
+    let mut builder = Response::builder().status(StatusCode::OK);
+    if let Some(attribution) = attribution {
+        builder = builder
+            .header(AUTHOR_HEADER, attribution.author.to_string())
+            .header(SIGNATURE_HEADER, attribution.signature_hex());
     }
 
-    StatusCode::NOT_FOUND.into_response()
+    if codec::wants_braid_json(request_headers) {
+        return builder
+            .header(http::header::CONTENT_TYPE, codec::BRAID_JSON)
+            .body(Body::from(serde_json::to_string(update).unwrap_or_default()))
+            .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+
+    if !update.version.is_empty() {
+        builder = builder.header("version", codec::format_versions(&update.version));
+    }
+    if !update.parents.is_empty() {
+        builder = builder.header("parents", codec::format_versions(&update.parents));
+    }
+    if let Some(merge_type) = &update.merge_type {
+        builder = builder.header("merge-type", merge_type.as_str());
+    }
+    if let Some(content_type) = &update.content_type {
+        builder = builder.header(http::header::CONTENT_TYPE, content_type.as_str());
+    }
+
+    let body = match &update.patches {
+        Some(patches) if !patches.is_empty() => {
+            builder = builder.header("patches", patches.len());
+            codec::encode_patches(patches)
+        }
+        _ => update.body.clone().unwrap_or_default(),
+    };
+
+    builder
+        .body(Body::from(body))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
 }
 
 /// PUT handler — stores a new update and broadcasts it via gossip.
+///
+/// The full Braid request is parsed (`Version`, `Parents`, `Merge-Type`,
+/// `Content-Type`, and `Content-Range`/`Patches` patch bodies); malformed
+/// requests are rejected with 400 and a description of the problem.
+///
+/// The update is checked against the ACL as the caller, but stored and
+/// gossiped as written by this node: the caller's key isn't available to
+/// sign with, and peers verify gossip against its signer. `get_author`
+/// and `Braid-Author` therefore name the relaying node, not the caller.
+#[tracing::instrument(
+    name = "braid_put",
+    skip_all,
+    fields(
+        url = %SubscriptionManager::normalize_url(&resource),
+        version = tracing::field::Empty,
+    )
+)]
 async fn handle_put(
     State(state): State<BraidAppState>,
+    RemotePeer(peer): RemotePeer,
     axum::extract::Path(resource): axum::extract::Path<String>,
     headers: HeaderMap,
-    body: String,
-) -> impl IntoResponse {
-    let url = format!("/{}", resource);
-
-    // Debug logging for Braid format
-    println!("\nINCOMING BRAID PUT:");
-    println!("PUT {} HTTP/3", url);
-    for (name, value) in &headers {
-        if name.as_str().starts_with("version")
-            || name.as_str().starts_with("parents")
-            || name.as_str().starts_with("merge-type")
-            || name.as_str().starts_with("content-type")
-            || name.as_str().starts_with("content-range")
-            || name.as_str().starts_with("braid-")
-        {
-            println!("{}: {}", name, value.to_str().unwrap_or("???"));
-        }
+    body: Bytes,
+) -> Response {
+    if resource.ends_with('/') {
+        return BraidIrohError::BadRequest("cannot PUT to a directory".to_string()).into_response();
     }
-    println!();
-    println!("{}", body);
-    println!("----------------------------------------\n");
+    let url = SubscriptionManager::normalize_url(&resource);
+
+    state.redaction.trace_body("incoming put body", &body);
 
     // Parse the incoming update from headers
-    let version = headers
-        .get("version")
-        .and_then(|v| v.to_str().ok())
-        .map(|v| Version::String(v.to_string()))
-        .unwrap_or_else(|| Version::String(uuid::Uuid::new_v4().to_string()));
-    let update = Update::snapshot(version, Bytes::from(body.clone().into_bytes()));
-
-    // Store locally
+    let mut update = match codec::parse_put(&headers, body) {
+        Ok(update) => update,
+        Err(e) => {
+            tracing::debug!("rejecting put: {}", e);
+            return BraidIrohError::from(e).into_response();
+        }
+    };
+    tracing::Span::current().record("version", version_string(&update).as_str());
+    tracing::debug!(
+        parents = %codec::format_versions(&update.parents),
+        merge_type = update.merge_type.as_deref().unwrap_or(""),
+        patches = update.patches.as_ref().map(|p| p.len()).unwrap_or(0),
+        "incoming put"
+    );
+    if let Err(e) = state.authorize_write(peer.as_ref(), &url, &update) {
+        tracing::debug!("rejecting put: {}", e);
+        return e.into_response();
+    }
+    state.default_parents(&url, &mut update);
+    assign_version(&mut update);
+
+    // This node vouches for updates it accepts over HTTP, so it signs
+    // them; the stored author is this node, whoever the caller was
+    let signed = match state.subscriptions.sign(&url, update) {
+        Ok(signed) => signed,
+        Err(e) => return e.into_response(),
+    };
+
     // Store locally (append to history)
-    let mut resources = state.resources.write().await;
-    resources
-        .entry(url.clone())
-        .or_insert_with(Vec::new)
-        .push(update.clone());
+    match state.ingest_attributed(&url, signed.update.clone(), Some(signed.attribution.clone())) {
+        Ok(true) => {}
+        Ok(false) => return StatusCode::OK.into_response(),
+        Err(e) => return e.into_response(),
+    }
 
-    // Broadcast to gossip subscribers
-    if let Err(e) = state.subscriptions.broadcast(&url, &update).await {
-        tracing::warn!("gossip broadcast failed for {}: {}", url, e);
+    // Broadcast to gossip subscribers, if we're on the resource's topic
+    match state.subscriptions.broadcast_signed(&url, &signed).await {
+        Ok(()) | Err(BraidIrohError::TopicNotFound(_)) => {}
+        Err(e) => tracing::warn!("gossip broadcast failed for {}: {}", url, e),
     }
 
-    StatusCode::OK
+    StatusCode::OK.into_response()
 }
 
 #[cfg(test)]
@@ -217,4 +550,180 @@ mod tests {
         }
         assert_handler_fn::<fn(BraidAppState) -> IrohAxum>();
     }
+
+    #[tokio::test]
+    async fn test_acl_is_checked_against_the_connecting_endpoint() {
+        use crate::acl::Principal;
+        use crate::discovery::DiscoveryConfig;
+        use crate::test_util::{h3_get, test_node};
+
+        let discovery = DiscoveryConfig::mock();
+        let alice = test_node(&discovery, Acl::new()).await;
+        let bob = test_node(&discovery, Acl::new()).await;
+        let acl = Acl::new().allow("/notes", Principal::Peer(alice.node_id()), Right::Read);
+        let server = test_node(&discovery, acl).await;
+        server
+            .put("/notes", Update::snapshot(Version::String("1".into()), Bytes::from("hi")))
+            .await
+            .unwrap();
+
+        assert_eq!(h3_get(&alice, server.node_id(), "/notes").await, StatusCode::OK);
+        assert_eq!(h3_get(&bob, server.node_id(), "/notes").await, StatusCode::FORBIDDEN);
+
+        for node in [alice, bob, server] {
+            node.shutdown().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_private_resources_need_an_explicit_grant() {
+        use crate::acl::Principal;
+        use crate::discovery::DiscoveryConfig;
+        use crate::private::ResourceSecret;
+        use crate::test_util::test_node;
+        use tower::ServiceExt;
+
+        let alice = iroh::SecretKey::from_bytes(&[2; 32]).public();
+        let bob = iroh::SecretKey::from_bytes(&[3; 32]).public();
+        let acl = Acl::open().allow("/secret", Principal::Peer(alice), Right::Read);
+        let node = test_node(&DiscoveryConfig::mock(), acl).await;
+        node.subscriptions().set_secret("/secret", ResourceSecret::generate());
+        for url in ["/public", "/secret"] {
+            let update = Update::snapshot(Version::String("1".into()), Bytes::from("hi"));
+            node.put(url, update).await.unwrap();
+        }
+
+        let app = router(node.app_state().clone());
+        let get = |path: &str, peer: Option<EndpointId>| {
+            let mut req = Request::get(path).body(Body::empty()).unwrap();
+            if let Some(peer) = peer {
+                req.extensions_mut().insert(RemoteId(peer));
+            }
+            app.clone().oneshot(req)
+        };
+        let listing = |response: Response| async move {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<Vec<String>>(&body).unwrap()
+        };
+
+        for peer in [None, Some(bob)] {
+            let response = get("/secret", peer).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let response = get("/", peer).await.unwrap();
+            assert_eq!(listing(response).await, vec!["/public"]);
+        }
+        assert_eq!(get("/secret", Some(alice)).await.unwrap().status(), StatusCode::OK);
+        let response = get("/", Some(alice)).await.unwrap();
+        assert_eq!(listing(response).await, vec!["/public", "/secret"]);
+
+        // `Acl::open()` lets anyone write, but not to a private resource.
+        let put = |path: &str, peer: EndpointId, version: &str| {
+            let mut req = Request::put(path)
+                .header("version", format!("\"{}\"", version))
+                .body(Body::from("hi"))
+                .unwrap();
+            req.extensions_mut().insert(RemoteId(peer));
+            app.clone().oneshot(req)
+        };
+        assert_eq!(put("/public", bob, "2").await.unwrap().status(), StatusCode::OK);
+        assert_eq!(put("/secret", bob, "2").await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(node.get_history("/secret").await, vec!["1"]);
+
+        node.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_subscription_streams_later_puts() {
+        use crate::discovery::DiscoveryConfig;
+        use crate::test_util::test_node;
+        use tower::ServiceExt;
+
+        let node = test_node(&DiscoveryConfig::mock(), Acl::open()).await;
+        let update = Update::snapshot(Version::String("1".into()), Bytes::from("first"));
+        node.put("/doc", update).await.unwrap();
+        let app = router(node.app_state().clone());
+
+        let req = Request::get("/doc").header("subscribe", "true").body(Body::empty());
+        let response = app.clone().oneshot(req.unwrap()).await.unwrap();
+        assert_eq!(response.status().as_u16(), 209);
+        let mut frames = response.into_body().into_data_stream();
+        let first = frames.next().await.unwrap().unwrap();
+        assert!(first.ends_with(b"\r\n\r\nfirst\r\n\r\n"), "{:?}", first);
+
+        let req = Request::put("/doc")
+            .header("version", "\"2\"")
+            .header("parents", "\"1\"")
+            .body(Body::from("second"));
+        assert_eq!(app.oneshot(req.unwrap()).await.unwrap().status(), StatusCode::OK);
+        let second = frames.next().await.unwrap().unwrap();
+        let second = String::from_utf8(second.to_vec()).unwrap();
+        assert!(second.starts_with("Version: \"2\"\r\nParents: \"1\"\r\n"), "{}", second);
+        assert!(second.ends_with("\r\n\r\nsecond\r\n\r\n"), "{}", second);
+
+        node.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_missing_resources_and_versions_are_errors() {
+        use crate::discovery::DiscoveryConfig;
+        use crate::test_util::test_node;
+        use tower::ServiceExt;
+
+        let node = test_node(&DiscoveryConfig::mock(), Acl::open()).await;
+        let update = Update::snapshot(Version::String("1".into()), Bytes::from("hi"));
+        node.put("/doc", update).await.unwrap();
+        let app = router(node.app_state().clone());
+        let send = |method: http::Method, path: &str| {
+            let req = Request::builder().method(method).uri(path);
+            app.clone().oneshot(req.body(Body::empty()).unwrap())
+        };
+        let text = |response: Response| async move {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        let response = send(http::Method::GET, "/doc?version=2").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(text(response).await, "version 2 not found for /doc");
+        let response = send(http::Method::GET, "/missing?history=true").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(text(response).await, "resource not found: /missing");
+        let response = send(http::Method::PUT, "/dir/").await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        node.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_puts_are_checked_against_the_caller() {
+        use crate::acl::Principal;
+        use crate::discovery::DiscoveryConfig;
+        use crate::test_util::test_node;
+        use tower::ServiceExt;
+
+        let alice = iroh::SecretKey::from_bytes(&[2; 32]).public();
+        let bob = iroh::SecretKey::from_bytes(&[3; 32]).public();
+        let acl = Acl::new()
+            .allow("/", Principal::Anyone, Right::Read)
+            .allow("/notes", Principal::Peer(alice), Right::Write);
+        let node = test_node(&DiscoveryConfig::mock(), acl).await;
+        let app = router(node.app_state().clone());
+        let put = |peer: Option<EndpointId>, version: &str| {
+            let mut req = Request::put("/notes")
+                .header("version", format!("\"{}\"", version))
+                .body(Body::from("hi"))
+                .unwrap();
+            if let Some(peer) = peer {
+                req.extensions_mut().insert(RemoteId(peer));
+            }
+            app.clone().oneshot(req)
+        };
+
+        assert_eq!(put(None, "1").await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(put(Some(bob), "2").await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(put(Some(alice), "3").await.unwrap().status(), StatusCode::OK);
+        assert_eq!(node.get_history("/notes").await, vec!["3"]);
+
+        node.shutdown().await.unwrap();
+    }
 }
@@ -1,8 +1,30 @@
 //! Optional TCP proxy bridge for legacy HTTP clients.
 //!
 //! Lets a browser or curl talk to the P2P Braid network through a local
-//! TCP listener. Requests to `http://localhost:<port>/resource` get
-//! forwarded over iroh to the target peer.
+//! TCP listener. Each request picks its target peer with, in order of
+//! precedence:
+//!
+//! - a `/peer/<endpoint-id>/...` path prefix (stripped before forwarding),
+//! - an `X-Braid-Peer: <endpoint-id>` header, or
+//! - a `Host` of `<endpoint-id>.localhost` (use the 52-character base32
+//!   form of the ID, which fits in a DNS label). Other `*.localhost`
+//!   names don't select a peer.
+//!
+//! Requests naming no peer go to the configured default peer, or are served
+//! by the local node itself when there is none. With `serve_local` set,
+//! requests for the default peer are also served locally when the node
+//! already has the resource, so a local web app keeps working without the
+//! network. Remote requests are forwarded over iroh HTTP/3; local ones run
+//! the node's Braid routes in process.
+//!
+//! A local request is checked against the ACL as an anonymous peer, so it
+//! gets whatever the `*` rules grant, like any HTTP client the node doesn't
+//! know. A request carrying the admin token (`Authorization: Bearer
+//! <token>`) runs as the node itself instead; the token is never forwarded
+//! to other peers. Peers see forwarded requests as coming from the node,
+//! so only requests carrying the token are forwarded at all. The node's metrics are served in Prometheus text format
+//! at `/_braid/metrics`, and, when an admin token is configured, the admin
+//! API under `/_braid/` (see `admin`).
 //!
 //! Requires the `proxy` feature flag.
 
@@ -11,86 +33,316 @@ pub mod bridge {
     use axum::{
         body::Body,
         extract::{Request, State},
-        response::Response,
-        routing::any,
+        response::{IntoResponse, Response},
+        routing::{any, get},
         Router,
     };
+    use axum::body::HttpBody;
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use http::{HeaderMap, Method, Uri};
+    use http_body::Frame;
+    use http_body_util::{combinators::BoxBody, StreamBody};
+    use iroh_h3_client::error::Error as H3Error;
     use iroh::{Endpoint, EndpointId};
+    use iroh_h3_axum::RemoteId;
     use iroh_h3_client::IrohH3Client;
-    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Instant;
+    use tokio_stream::wrappers::ReceiverStream;
+    use tokio_util::sync::CancellationToken;
+    use tower::ServiceExt;
     use tower_http::trace::TraceLayer;
 
+    use crate::admin::{self, AdminState};
+    use crate::error::{BraidIrohError, Result};
+    use crate::metrics::Metrics;
+    use crate::node::ProxyConfig;
+    use crate::protocol::{self, BraidAppState};
+    use crate::subscription::SubscriptionManager;
+
+    /// Path on the proxy listener serving Prometheus metrics.
+    pub const METRICS_PATH: &str = "/_braid/metrics";
+
+    /// Path prefix selecting the target peer: `/peer/<endpoint-id>/...`.
+    pub const PEER_PATH_PREFIX: &str = "/peer/";
+
+    /// Request header selecting the target peer.
+    pub const PEER_HEADER: &str = "x-braid-peer";
+
     /// State shared across proxy request handlers.
     #[derive(Clone)]
     pub struct ProxyState {
         client: IrohH3Client,
-        default_peer: EndpointId,
+        /// The node's own Braid routes, for requests it serves itself.
+        local: axum::Router,
+        app_state: BraidAppState,
+        local_id: EndpointId,
+        default_peer: Option<EndpointId>,
+        serve_local: bool,
+        /// Local requests bearing this token run as the node.
+        admin_token: Option<Arc<str>>,
+        metrics: Arc<Metrics>,
+        /// Ends open subscriptions when the proxy shuts down.
+        shutdown: CancellationToken,
     }
 
     impl ProxyState {
-        /// Create a new proxy state with the given client and default peer.
-        pub fn new(client: IrohH3Client, default_peer: EndpointId) -> Self {
+        /// Create a new proxy state for the node behind `endpoint`.
+        /// Requests naming no peer go to `config.default_peer`, or to the
+        /// node itself if that is `None`.
+        pub fn new(
+            client: IrohH3Client,
+            endpoint: &Endpoint,
+            app_state: BraidAppState,
+            config: &ProxyConfig,
+            shutdown: CancellationToken,
+        ) -> Self {
             Self {
                 client,
-                default_peer,
+                metrics: app_state.metrics.clone(),
+                local: protocol::router(app_state.clone()),
+                app_state,
+                local_id: endpoint.id(),
+                default_peer: config.default_peer,
+                serve_local: config.serve_local,
+                admin_token: config.admin_token.as_deref().map(Arc::from),
+                shutdown,
+            }
+        }
+
+        /// Whether a request presents the admin token.
+        fn is_admin(&self, headers: &HeaderMap) -> bool {
+            self.admin_token
+                .as_deref()
+                .is_some_and(|token| admin::authorized(headers, token))
+        }
+
+        /// Whether the local node can answer a request itself: a read of a
+        /// resource it stores, or a write to one it stores and gossips
+        /// (the write reaches the other subscribers over gossip).
+        async fn has_locally(&self, method: &Method, path: &str) -> bool {
+            let path = path.split_once('?').map_or(path, |(path, _query)| path);
+            if path.ends_with('/') {
+                return false;
+            }
+            let url = SubscriptionManager::normalize_url(path);
+            if self.app_state.store.latest(&url).is_none() {
+                return false;
             }
+            match *method {
+                Method::GET | Method::HEAD => true,
+                Method::PUT => self.app_state.subscriptions.is_subscribed(&url).await,
+                _ => false,
+            }
+        }
+    }
+
+    /// Where a proxied request goes.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Target {
+        /// The node running the proxy.
+        Local,
+        /// Another peer, over HTTP/3.
+        Peer(EndpointId),
+    }
+
+    /// Pick the target of a request and the path (with query) to send it
+    /// to. See the module docs for the rules.
+    pub fn route(
+        uri: &Uri,
+        headers: &HeaderMap,
+        local_id: EndpointId,
+        default_peer: Option<EndpointId>,
+    ) -> Result<(Target, String)> {
+        let (peer, path) = requested_peer(uri, headers)?;
+        Ok((target_for(peer.or(default_peer), local_id), path))
+    }
+
+    /// The peer a request names explicitly, if any, and the path (with
+    /// query) to send it to.
+    pub fn requested_peer(uri: &Uri, headers: &HeaderMap) -> Result<(Option<EndpointId>, String)> {
+        let mut path = uri.path().to_string();
+        let mut peer = None;
+
+        if let Some(rest) = path.strip_prefix(PEER_PATH_PREFIX) {
+            let (id, tail) = rest.split_once('/').unwrap_or((rest, ""));
+            peer = Some(parse_peer(id)?);
+            path = format!("/{}", tail);
+        } else if let Some(value) = headers.get(PEER_HEADER) {
+            let id = value
+                .to_str()
+                .map_err(|_| BraidIrohError::InvalidPeer("non-ASCII header".to_string()))?;
+            peer = Some(parse_peer(id.trim())?);
+        } else if let Some(host) = headers.get(http::header::HOST).and_then(|h| h.to_str().ok()) {
+            let host = host.rsplit_once(':').map_or(host, |(name, _port)| name);
+            // `app.localhost` and the like are just names for this proxy
+            peer = host.strip_suffix(".localhost").and_then(|id| id.parse().ok());
+        }
+
+        if let Some(query) = uri.query() {
+            path = format!("{}?{}", path, query);
+        }
+        Ok((peer, path))
+    }
+
+    fn target_for(peer: Option<EndpointId>, local_id: EndpointId) -> Target {
+        match peer {
+            Some(id) if id != local_id => Target::Peer(id),
+            _ => Target::Local,
         }
     }
 
+    fn parse_peer(id: &str) -> Result<EndpointId> {
+        id.parse()
+            .map_err(|_| BraidIrohError::InvalidPeer(id.to_string()))
+    }
+
     /// Start a local TCP proxy that forwards HTTP/1.1 requests to a target Braid peer
     /// over HTTP/3.
     ///
     /// # Arguments
     /// * `endpoint` - The iroh endpoint to use for P2P connections
-    /// * `listen_addr` - Local socket address to bind the HTTP/1.1 listener
-    /// * `default_peer` - The target peer ID to forward requests to
+    /// * `config` - Listen address, default peer and local serving policy
+    /// * `app_state` - The local node's state, for requests it serves itself;
+    ///   its metrics are updated per request and served at `METRICS_PATH`
+    /// * `shutdown` - Cancelling this stops accepting connections; requests
+    ///   already in flight are allowed to finish
     ///
     /// # Returns
     /// Returns `Ok(())` when the server shuts down gracefully, or an error if binding fails.
     pub async fn start_proxy(
         endpoint: &Endpoint,
-        listen_addr: SocketAddr,
-        default_peer: EndpointId,
-    ) -> anyhow::Result<()> {
+        config: ProxyConfig,
+        app_state: BraidAppState,
+        admin: Option<AdminState>,
+        shutdown: CancellationToken,
+    ) -> Result<()> {
         let alpn = super::super::node::BRAID_H3_ALPN.to_vec();
         let client = IrohH3Client::new(endpoint.clone(), alpn);
+        let listen_addr = config.listen_addr;
 
-        let state = ProxyState::new(client, default_peer);
+        let state = ProxyState::new(client, endpoint, app_state, &config, shutdown.clone());
 
-        let app = Router::new()
+        let mut app = Router::new()
+            .route(METRICS_PATH, get(metrics_handler))
+            .route("/", any(proxy_handler))
             .route("/{*path}", any(proxy_handler))
-            .with_state(state)
-            .layer(TraceLayer::new_for_http());
+            .with_state(state);
+        if let Some(admin) = admin {
+            app = app.merge(admin::router(admin));
+        }
+        let app = app.layer(TraceLayer::new_for_http());
 
         tracing::info!("Starting TCP Proxy Bridge on http://{}", listen_addr);
 
-        let listener = tokio::net::TcpListener::bind(listen_addr).await?;
-        axum::serve(listener, app).await?;
+        let listener = tokio::net::TcpListener::bind(listen_addr)
+            .await
+            .map_err(|e| BraidIrohError::Proxy(format!("failed to bind {}: {}", listen_addr, e)))?;
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await
+            .map_err(BraidIrohError::proxy)?;
 
+        tracing::info!("TCP Proxy Bridge on http://{} stopped", listen_addr);
         Ok(())
     }
 
-    /// Main proxy handler that forwards HTTP/1.1 requests to the P2P network via HTTP/3.
+    /// Serve the node's metrics in Prometheus text format.
+    async fn metrics_handler(State(state): State<ProxyState>) -> Response {
+        (
+            [(http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            state.metrics.render_prometheus(),
+        )
+            .into_response()
+    }
+
+    /// Forward a request and record its outcome and latency.
+    async fn proxy_handler(State(state): State<ProxyState>, req: Request) -> Result<Response> {
+        let started = Instant::now();
+        state.metrics.proxy_requests.inc();
+        let result = forward(&state, req).await;
+        state.metrics.proxy_request_duration.observe(started.elapsed());
+        if result.is_err() {
+            state.metrics.proxy_errors.inc();
+        }
+        result.map(|response| end_on_shutdown(response, &state.shutdown))
+    }
+
+    /// Subscription (209) bodies never end on their own; cut them when the
+    /// proxy shuts down so graceful shutdown doesn't wait on them forever.
+    fn end_on_shutdown(response: Response, shutdown: &CancellationToken) -> Response {
+        if response.status().as_u16() != 209 {
+            return response;
+        }
+        let shutdown = shutdown.clone().cancelled_owned();
+        response.map(|body| Body::from_stream(body.into_data_stream().take_until(shutdown)))
+    }
+
+    /// Send a request to its target: the local node or a remote peer.
+    /// Requests for the default peer stay local if `serve_local` is set
+    /// and the node has the resource; explicitly named peers are always
+    /// asked.
+    ///
+    /// Peers see forwarded requests as coming from the node, so only
+    /// requests with the admin token are forwarded; the rest are 403.
+    async fn forward(state: &ProxyState, req: Request) -> Result<Response> {
+        let (peer, path) = requested_peer(req.uri(), req.headers())?;
+        let peer = match peer {
+            Some(peer) => Some(peer),
+            None if state.serve_local && state.has_locally(req.method(), &path).await => None,
+            None => state.default_peer,
+        };
+        match target_for(peer, state.local_id) {
+            Target::Local => serve_local(state, req, &path).await,
+            Target::Peer(peer) if state.is_admin(req.headers()) => {
+                forward_to_peer(state, req, peer, &path).await
+            }
+            Target::Peer(peer) => Err(BraidIrohError::Forbidden(format!(
+                "forwarding to {} needs the admin token",
+                peer
+            ))),
+        }
+    }
+
+    /// Run a request through the node's own Braid routes: as the node if it
+    /// presents the admin token, anonymously otherwise.
+    async fn serve_local(state: &ProxyState, mut req: Request, path: &str) -> Result<Response> {
+        let as_node = state.is_admin(req.headers());
+        tracing::info!(method = %req.method(), path = %path, as_node, "serving request locally");
+        *req.uri_mut() = path.parse().map_err(BraidIrohError::proxy)?;
+        req.headers_mut().remove(PEER_HEADER);
+        if as_node {
+            req.extensions_mut().insert(RemoteId(state.local_id));
+        }
+        let response = state.local.clone().oneshot(req).await;
+        Ok(match response {
+            Ok(response) => response,
+            Err(infallible) => match infallible {},
+        })
+    }
+
+    /// Forward an HTTP/1.1 request to a peer via HTTP/3.
     ///
     /// This handler:
-    /// 1. Reconstructs the target URL from the default peer and request path
-    /// 2. Converts the incoming axum body to bytes for forwarding
+    /// 1. Reconstructs the target URL from the peer and request path
+    /// 2. Streams the incoming axum body into the HTTP/3 request
     /// 3. Sends the request via IrohH3Client
     /// 4. Streams the response back to the HTTP/1.1 client
-    async fn proxy_handler(
-        State(state): State<ProxyState>,
+    ///
+    /// Neither body is buffered: chunks are passed on as the other side
+    /// reads them, so a 209 subscription stays open for as long as both
+    /// ends do, and a slow reader slows the writer down instead of filling
+    /// memory.
+    async fn forward_to_peer(
+        state: &ProxyState,
         req: Request,
-    ) -> Result<Response, String> {
-        let path = req.uri().path();
-        let query = req
-            .uri()
-            .query()
-            .map(|q| format!("?{}", q))
-            .unwrap_or_default();
-
+        peer: EndpointId,
+        path: &str,
+    ) -> Result<Response> {
         // Construct target URL: https://<peer_id>/<path>?<query>
         // The peer_id is used as the authority/host in the URL
-        let target_url = format!("https://{}{}{}", state.default_peer, path, query);
+        let target_url = format!("https://{}{}", peer, path);
 
         tracing::info!(
             method = %req.method(),
@@ -99,26 +351,23 @@ pub mod bridge {
             "Proxying request"
         );
 
-        // Convert incoming axum body to bytes
-        // Note: We collect the entire body for simplicity. For large uploads,
-        // streaming would be more efficient but requires more complex handling.
         let (parts, body) = req.into_parts();
-        let body_bytes = axum::body::to_bytes(body, 10 * 1024 * 1024) // 10MB limit
-            .await
-            .map_err(|e| format!("Failed to read request body: {}", e))?;
+        let has_body = !body.is_end_stream();
 
         // Build the request using IrohH3Client
         let mut builder = state
             .client
             .request(parts.method.clone(), &target_url);
 
-        // Copy headers from the original request, skipping hop-by-hop headers
+        // Copy headers from the original request, skipping hop-by-hop
+        // headers and the admin token, which is for this node only
         for (key, value) in &parts.headers {
             let key_str = key.as_str().to_lowercase();
             // Skip hop-by-hop headers that shouldn't be forwarded
             if matches!(
                 key_str.as_str(),
                 "host"
+                    | PEER_HEADER
                     | "connection"
                     | "keep-alive"
                     | "proxy-authenticate"
@@ -127,40 +376,33 @@ pub mod bridge {
                     | "trailers"
                     | "transfer-encoding"
                     | "upgrade"
-            ) {
+            ) || key == http::header::AUTHORIZATION
+            {
                 continue;
             }
             builder = builder.header(key, value);
         }
 
-        // Set the body and build the request
-        // Use the .bytes() method on RequestBuilder to set body with octet-stream content-type
-        let client_req = if body_bytes.is_empty() {
-            builder.build().map_err(|e| format!("Failed to build request: {}", e))?
+        // Set the body and build the request. The body is streamed, chunk
+        // by chunk, as the H3 request is sent.
+        let client_req = if has_body {
+            builder
+                .body(request_body(body).into())
+                .map_err(BraidIrohError::peer)?
         } else {
-            builder.bytes(body_bytes).map_err(|e| format!("Failed to build request: {}", e))?
+            builder.build().map_err(BraidIrohError::peer)?
         };
 
         let resp = client_req
             .send()
             .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+            .map_err(BraidIrohError::peer)?;
 
         // Extract response parts
         let status = resp.status;
         let headers = resp.headers.clone();
 
-        // Collect response body
-        let response_bytes = resp
-            .bytes()
-            .await
-            .map_err(|e| format!("Failed to read response body: {}", e))?;
-
-        tracing::debug!(
-            status = %status,
-            body_len = response_bytes.len(),
-            "Received response from P2P network"
-        );
+        tracing::debug!(status = %status, "Received response from P2P network");
 
         // Build the response
         let mut response_builder = Response::builder().status(status);
@@ -168,7 +410,7 @@ pub mod bridge {
         // Copy response headers
         let response_headers = response_builder
             .headers_mut()
-            .ok_or_else(|| "Failed to get response headers mut".to_string())?;
+            .ok_or_else(|| BraidIrohError::proxy("invalid upstream response"))?;
 
         for (key, value) in headers.iter() {
             let key_str = key.as_str().to_lowercase();
@@ -185,9 +427,32 @@ pub mod bridge {
             response_headers.insert(key, value.clone());
         }
 
+        // Pass the response body on as it arrives
+        let chunks = resp
+            .bytes_stream()
+            .map(|chunk| chunk.map_err(|e| std::io::Error::other(e.to_string())));
         response_builder
-            .body(Body::from(response_bytes))
-            .map_err(|e| format!("Failed to build response: {}", e))
+            .body(Body::from_stream(chunks))
+            .map_err(BraidIrohError::proxy)
+    }
+
+    /// Hand a request body to the H3 client, which needs a `Sync` body:
+    /// chunks are passed through a small channel as they arrive.
+    fn request_body(body: Body) -> BoxBody<Bytes, H3Error> {
+        let (tx, rx) = tokio::sync::mpsc::channel(4);
+        tokio::spawn(async move {
+            let mut chunks = body.into_data_stream();
+            while let Some(chunk) = chunks.next().await {
+                let chunk = chunk
+                    .map(Frame::data)
+                    .map_err(|e| H3Error::Other(e.to_string()));
+                let failed = chunk.is_err();
+                if tx.send(chunk).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        BoxBody::new(StreamBody::new(ReceiverStream::new(rx)))
     }
 
     #[cfg(test)]
@@ -200,23 +465,314 @@ pub mod bridge {
             fn assert_proxy_state<T: Clone + Send + Sync + 'static>() {}
             assert_proxy_state::<ProxyState>();
         }
+
+        fn id(seed: u8) -> EndpointId {
+            iroh::SecretKey::from_bytes(&[seed; 32]).public()
+        }
+
+        fn route_of(uri: &str, headers: &[(&'static str, String)]) -> Result<(Target, String)> {
+            let mut map = HeaderMap::new();
+            for (name, value) in headers {
+                map.insert(*name, value.parse().unwrap());
+            }
+            route(&uri.parse().unwrap(), &map, id(1), None)
+        }
+
+        #[tokio::test]
+        async fn test_local_requests_are_anonymous_without_the_admin_token() {
+            use crate::acl::{Acl, Principal, Right};
+            use crate::discovery::DiscoveryConfig;
+            use crate::test_util::test_node;
+            use braid_http_rs::{Update, Version};
+            use http::StatusCode;
+
+            let acl = Acl::new().allow("/public", Principal::Anyone, Right::Read);
+            let node = test_node(&DiscoveryConfig::mock(), acl).await;
+            for url in ["/public", "/private"] {
+                let update = Update::snapshot(Version::String("1".into()), Bytes::from("hi"));
+                node.put(url, update).await.unwrap();
+            }
+            let config = ProxyConfig {
+                listen_addr: "127.0.0.1:0".parse().unwrap(),
+                admin_token: Some("s3cret".to_string()),
+                default_peer: None,
+                serve_local: true,
+            };
+            let client = IrohH3Client::new(node.endpoint().clone(), b"unused".to_vec());
+            let state = ProxyState::new(
+                client,
+                node.endpoint(),
+                node.app_state().clone(),
+                &config,
+                CancellationToken::new(),
+            );
+            let get = |path: &str, token: Option<&str>| {
+                let mut req = Request::get(path);
+                if let Some(token) = token {
+                    req = req.header(http::header::AUTHORIZATION, format!("Bearer {}", token));
+                }
+                forward(&state, req.body(Body::empty()).unwrap())
+            };
+
+            assert_eq!(get("/public", None).await.unwrap().status(), StatusCode::OK);
+            assert_eq!(get("/private", None).await.unwrap().status(), StatusCode::FORBIDDEN);
+            assert_eq!(
+                get("/private", Some("wrong")).await.unwrap().status(),
+                StatusCode::FORBIDDEN
+            );
+            assert_eq!(get("/private", Some("s3cret")).await.unwrap().status(), StatusCode::OK);
+
+            node.shutdown().await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_requests_reach_the_peer_they_name() {
+            use crate::acl::Acl;
+            use crate::discovery::DiscoveryConfig;
+            use crate::node::BRAID_H3_ALPN;
+            use crate::test_util::test_node;
+            use braid_http_rs::{Update, Version};
+            use http::StatusCode;
+
+            let discovery = DiscoveryConfig::mock();
+            let node = test_node(&discovery, Acl::open()).await;
+            let remote = test_node(&discovery, Acl::open()).await;
+            for (peer, body) in [(&node, "local"), (&remote, "remote")] {
+                let update = Update::snapshot(Version::String("1".into()), Bytes::from(body));
+                peer.put("/doc", update.clone()).await.unwrap();
+                peer.put(&format!("/{}-only", body), update).await.unwrap();
+            }
+            let config = ProxyConfig {
+                listen_addr: "127.0.0.1:0".parse().unwrap(),
+                admin_token: Some("s3cret".to_string()),
+                default_peer: Some(remote.node_id()),
+                serve_local: true,
+            };
+            let client = IrohH3Client::new(node.endpoint().clone(), BRAID_H3_ALPN.to_vec());
+            let state = ProxyState::new(
+                client,
+                node.endpoint(),
+                node.app_state().clone(),
+                &config,
+                CancellationToken::new(),
+            );
+            let get = |path: String, peer_header: Option<EndpointId>| {
+                let mut req =
+                    Request::get(path).header(http::header::AUTHORIZATION, "Bearer s3cret");
+                if let Some(peer) = peer_header {
+                    req = req.header(PEER_HEADER, peer.to_string());
+                }
+                let req = req.body(Body::empty()).unwrap();
+                let state = state.clone();
+                async move {
+                    let response = forward(&state, req).await.unwrap();
+                    let status = response.status();
+                    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await;
+                    (status, String::from_utf8(body.unwrap().to_vec()).unwrap())
+                }
+            };
+            let ok = |body: &str| (StatusCode::OK, body.to_string());
+
+            // Stored locally: no hop. Only the default peer has it: forwarded.
+            assert_eq!(get("/doc".into(), None).await, ok("local"));
+            assert_eq!(get("/remote-only".into(), None).await, ok("remote"));
+            // A named peer is always asked, by path prefix or header.
+            let prefixed = format!("/peer/{}/doc", remote.node_id());
+            assert_eq!(get(prefixed, None).await, ok("remote"));
+            assert_eq!(get("/doc".into(), Some(remote.node_id())).await, ok("remote"));
+            // Naming the proxy's own node is served locally.
+            let own = format!("/peer/{}/remote-only", node.node_id());
+            assert_eq!(get(own, None).await.0, StatusCode::NOT_FOUND);
+
+            node.shutdown().await.unwrap();
+            remote.shutdown().await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_forwarding_needs_the_admin_token() {
+            use crate::acl::{Acl, Principal, Right};
+            use crate::discovery::DiscoveryConfig;
+            use crate::node::BRAID_H3_ALPN;
+            use crate::test_util::test_node;
+
+            let discovery = DiscoveryConfig::mock();
+            let node = test_node(&discovery, Acl::open()).await;
+            // The remote only lets the proxy's node write.
+            let acl = Acl::new()
+                .allow("/", Principal::Anyone, Right::Read)
+                .allow("/", Principal::Peer(node.node_id()), Right::Write);
+            let remote = test_node(&discovery, acl).await;
+            let config = ProxyConfig {
+                listen_addr: "127.0.0.1:0".parse().unwrap(),
+                admin_token: Some("s3cret".to_string()),
+                default_peer: Some(remote.node_id()),
+                serve_local: false,
+            };
+            let client = IrohH3Client::new(node.endpoint().clone(), BRAID_H3_ALPN.to_vec());
+            let state = ProxyState::new(
+                client,
+                node.endpoint(),
+                node.app_state().clone(),
+                &config,
+                CancellationToken::new(),
+            );
+            let put = |token: Option<&str>| {
+                let mut req = Request::put("/doc").header("version", "\"1\"");
+                if let Some(token) = token {
+                    req = req.header(http::header::AUTHORIZATION, format!("Bearer {}", token));
+                }
+                forward(&state, req.body(Body::from("hi")).unwrap())
+            };
+
+            for token in [None, Some("wrong")] {
+                let err = put(token).await.unwrap_err();
+                assert!(matches!(err, BraidIrohError::Forbidden(_)), "{err}");
+            }
+            assert!(remote.get("/doc").await.is_none());
+            assert!(put(Some("s3cret")).await.unwrap().status().is_success());
+            assert_eq!(remote.get("/doc").await.unwrap().body.unwrap(), Bytes::from("hi"));
+
+            node.shutdown().await.unwrap();
+            remote.shutdown().await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_bodies_stream_through_the_bridge() {
+            use crate::acl::Acl;
+            use crate::discovery::DiscoveryConfig;
+            use crate::node::BRAID_H3_ALPN;
+            use crate::test_util::test_node;
+            use braid_http_rs::{Update, Version};
+            use std::time::Duration;
+
+            let discovery = DiscoveryConfig::mock();
+            let node = test_node(&discovery, Acl::open()).await;
+            let remote = test_node(&discovery, Acl::open()).await;
+            let config = ProxyConfig {
+                listen_addr: "127.0.0.1:0".parse().unwrap(),
+                admin_token: Some("s3cret".to_string()),
+                default_peer: Some(remote.node_id()),
+                serve_local: false,
+            };
+            let client = IrohH3Client::new(node.endpoint().clone(), BRAID_H3_ALPN.to_vec());
+            let state = ProxyState::new(
+                client,
+                node.endpoint(),
+                node.app_state().clone(),
+                &config,
+                CancellationToken::new(),
+            );
+            let request = |method: Method| {
+                Request::builder()
+                    .method(method)
+                    .uri("/doc")
+                    .header(http::header::AUTHORIZATION, "Bearer s3cret")
+            };
+
+            // A PUT body sent in many chunks arrives whole.
+            let expected: Vec<u8> = (0..8).flat_map(|i| vec![b'a' + i; 8 * 1024]).collect();
+            let chunks: Vec<std::io::Result<Bytes>> = expected
+                .chunks(8 * 1024)
+                .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+                .collect();
+            let put = request(Method::PUT)
+                .header("version", "\"1\"")
+                .body(Body::from_stream(futures::stream::iter(chunks)))
+                .unwrap();
+            assert!(forward(&state, put).await.unwrap().status().is_success());
+            assert_eq!(remote.get("/doc").await.unwrap().body, Some(Bytes::from(expected)));
+
+            // A subscription keeps delivering after its headers arrived.
+            let get = request(Method::GET).header("subscribe", "true");
+            let response = forward(&state, get.body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status().as_u16(), 209);
+            let mut body = response.into_body().into_data_stream();
+            let update = Update::snapshot(Version::String("2".into()), Bytes::from("second"));
+            remote.put("/doc", update).await.unwrap();
+            let mut received = Vec::new();
+            let reading = async {
+                while !received.ends_with(b"second\r\n\r\n") {
+                    received.extend_from_slice(&body.next().await.unwrap().unwrap());
+                }
+            };
+            tokio::time::timeout(Duration::from_secs(10), reading)
+                .await
+                .expect("the second update comes through");
+            drop(body);
+
+            node.shutdown().await.unwrap();
+            remote.shutdown().await.unwrap();
+        }
+
+        #[test]
+        fn test_route_by_path_prefix() {
+            let uri = format!("/peer/{}/docs/a?x=1", id(2));
+            assert_eq!(
+                route_of(&uri, &[]).unwrap(),
+                (Target::Peer(id(2)), "/docs/a?x=1".to_string())
+            );
+            let uri = format!("/peer/{}", id(2));
+            assert_eq!(route_of(&uri, &[]).unwrap().1, "/");
+        }
+
+        #[test]
+        fn test_route_by_header_and_host() {
+            assert_eq!(
+                route_of("/doc", &[("x-braid-peer", id(2).to_string())]).unwrap(),
+                (Target::Peer(id(2)), "/doc".to_string())
+            );
+            assert_eq!(
+                route_of("/doc", &[("host", format!("{}.localhost:8080", id(3)))]).unwrap(),
+                (Target::Peer(id(3)), "/doc".to_string())
+            );
+            assert!(matches!(
+                route_of("/doc", &[("x-braid-peer", "bogus".to_string())]),
+                Err(BraidIrohError::InvalidPeer(_))
+            ));
+            assert_eq!(
+                route_of("/doc", &[("host", "app.localhost:8080".to_string())]).unwrap(),
+                (Target::Local, "/doc".to_string())
+            );
+        }
+
+        #[test]
+        fn test_route_falls_back_to_default_then_local() {
+            assert_eq!(
+                route_of("/doc", &[("host", "localhost:8080".to_string())]).unwrap(),
+                (Target::Local, "/doc".to_string())
+            );
+            let (target, _) =
+                route(&"/doc".parse().unwrap(), &HeaderMap::new(), id(1), Some(id(2))).unwrap();
+            assert_eq!(target, Target::Peer(id(2)));
+            // Naming ourselves is served locally; iroh can't dial itself.
+            let uri = format!("/peer/{}/doc", id(1));
+            assert_eq!(route_of(&uri, &[]).unwrap().0, Target::Local);
+        }
     }
 }
 
 #[cfg(not(feature = "proxy"))]
 pub mod bridge {
     //! Stub implementation when proxy feature is disabled.
-    use iroh::{Endpoint, EndpointId};
-    use std::net::SocketAddr;
+    use iroh::Endpoint;
+
+    use crate::error::{BraidIrohError, Result};
+    use tokio_util::sync::CancellationToken;
+
+    use crate::admin::AdminState;
+    use crate::node::ProxyConfig;
+    use crate::protocol::BraidAppState;
 
     /// Stub function that returns an error when proxy feature is not enabled.
     pub async fn start_proxy(
         _endpoint: &Endpoint,
-        _listen_addr: SocketAddr,
-        _default_peer: EndpointId,
-    ) -> anyhow::Result<()> {
-        Err(anyhow::anyhow!(
-            "Proxy feature is not enabled. Rebuild with --features proxy"
+        _config: ProxyConfig,
+        _app_state: BraidAppState,
+        _admin: Option<AdminState>,
+        _shutdown: CancellationToken,
+    ) -> Result<()> {
+        Err(BraidIrohError::proxy(
+            "Proxy feature is not enabled. Rebuild with --features proxy",
         ))
     }
 }
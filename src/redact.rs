@@ -0,0 +1,90 @@
+//! Payload redaction for logs.
+//!
+//! Resource bodies are user documents and must not end up in service logs
+//! by accident. Structured events carry only metadata (URL, topic, peer,
+//! version, sizes); bodies are logged separately at `TRACE` and pass
+//! through the node's `RedactionPolicy` first.
+
+use std::fmt;
+
+/// How resource bodies appear in `TRACE`-level logs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RedactionPolicy {
+    /// Never log bodies.
+    Omit,
+    /// Log only the size and a short blake3 digest, enough to correlate the
+    /// same payload across peers without revealing it.
+    #[default]
+    Digest,
+    /// Log bodies in full (lossy UTF-8). For local debugging only.
+    Full,
+}
+
+impl RedactionPolicy {
+    /// Emit `body` as a `TRACE` event according to this policy.
+    ///
+    /// Does nothing unless `TRACE` is enabled for this crate, so the digest
+    /// is never computed in normal operation.
+    pub fn trace_body(&self, label: &'static str, body: &[u8]) {
+        if *self == RedactionPolicy::Omit || !tracing::enabled!(tracing::Level::TRACE) {
+            return;
+        }
+        tracing::trace!(body = %self.render(body), "{}", label);
+    }
+
+    /// Render `body` for logging according to this policy.
+    pub fn render<'a>(&self, body: &'a [u8]) -> Redacted<'a> {
+        Redacted {
+            policy: *self,
+            body,
+        }
+    }
+}
+
+/// A body formatted according to a `RedactionPolicy`.
+pub struct Redacted<'a> {
+    policy: RedactionPolicy,
+    body: &'a [u8],
+}
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.policy {
+            RedactionPolicy::Omit => write!(f, "<redacted>"),
+            RedactionPolicy::Digest => {
+                let hash = blake3::hash(self.body);
+                write!(
+                    f,
+                    "<{} bytes, blake3:{}>",
+                    self.body.len(),
+                    &hash.to_hex()[..16]
+                )
+            }
+            RedactionPolicy::Full => write!(f, "{}", String::from_utf8_lossy(self.body)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_policies() {
+        let body = b"secret plans";
+        assert_eq!(RedactionPolicy::Omit.render(body).to_string(), "<redacted>");
+        assert_eq!(
+            RedactionPolicy::Full.render(body).to_string(),
+            "secret plans"
+        );
+
+        let digest = RedactionPolicy::Digest.render(body).to_string();
+        assert!(digest.starts_with("<12 bytes, blake3:"));
+        assert!(!digest.contains("secret"));
+    }
+
+    #[test]
+    fn test_default_is_digest() {
+        assert_eq!(RedactionPolicy::default(), RedactionPolicy::Digest);
+    }
+}
@@ -0,0 +1,667 @@
+//! Pluggable resource storage for braid_iroh.
+//!
+//! Every node keeps the full version history of the resources it has seen.
+//! `ResourceStore` is the interface the node and the protocol handlers go
+//! through; `MemoryStore` keeps everything in RAM (tests, demos) and
+//! `FileStore` persists each resource as an append-only log on disk so
+//! history survives a restart.
+
+use braid_http_rs::Update;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::dag::VersionGraph;
+use crate::error::Result;
+use crate::signing::Attribution;
+
+/// Storage backend for Braid resources and their version history.
+///
+/// URLs passed in are expected to be normalized already
+/// (see `SubscriptionManager::normalize_url`).
+pub trait ResourceStore: Send + Sync {
+    /// Add an update to a resource's version graph. Returns `false`, and
+    /// stores nothing, if its version is already present.
+    fn append(&self, url: &str, update: Update) -> Result<bool> {
+        self.append_attributed(url, update, None)
+    }
+
+    /// Add an update together with its verified author. The check for an
+    /// existing version and the write are one step, so concurrent appends
+    /// of the same version store it once and only one of them returns
+    /// `true`.
+    fn append_attributed(
+        &self,
+        url: &str,
+        update: Update,
+        attribution: Option<Attribution>,
+    ) -> Result<bool>;
+
+    /// The verified author of a version, if it was signed.
+    fn attribution(&self, url: &str, version_id: &str) -> Option<Attribution>;
+
+    /// The update at the head of the resource's version graph.
+    fn latest(&self, url: &str) -> Option<Update>;
+
+    /// The update carrying the given version ID.
+    fn get_version(&self, url: &str, version_id: &str) -> Option<Update>;
+
+    /// Full history of a resource, parents before children.
+    fn history(&self, url: &str) -> Vec<Update>;
+
+    /// Current heads of the resource's version graph.
+    fn frontier(&self, url: &str) -> Vec<String>;
+
+    /// A snapshot of the resource's whole version graph.
+    fn graph(&self, url: &str) -> Option<VersionGraph>;
+
+    /// All resource URLs known to this store.
+    fn list(&self) -> Vec<String>;
+
+    /// Make every accepted update durable and release open handles.
+    /// Called once when the node shuts down.
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Which storage backend a node should use.
+#[derive(Clone, Debug, Default)]
+pub enum StorageConfig {
+    /// Keep everything in memory. History is lost when the process exits.
+    #[default]
+    Memory,
+    /// Persist every resource as an append-only log under this directory.
+    Disk(PathBuf),
+}
+
+impl StorageConfig {
+    /// Open the configured backend.
+    pub fn open(&self) -> Result<Arc<dyn ResourceStore>> {
+        match self {
+            StorageConfig::Memory => Ok(Arc::new(MemoryStore::new())),
+            StorageConfig::Disk(dir) => Ok(Arc::new(FileStore::open(dir)?)),
+        }
+    }
+}
+
+/// Render the version IDs of an update as a single comma-separated string.
+pub fn version_string(update: &Update) -> String {
+    update
+        .version
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Directory-style listing of the resources under `prefix`.
+///
+/// Without `recursive`, only the immediate children are returned: resources
+/// directly under the prefix, and one `<prefix>/<name>/` entry for each
+/// deeper level. With `recursive`, every resource under the prefix.
+pub fn list_directory(store: &dyn ResourceStore, prefix: &str, recursive: bool) -> Vec<String> {
+    let prefix = match prefix.trim_end_matches('/') {
+        "" => "/".to_string(),
+        p => format!("{}/", p),
+    };
+
+    let mut entries: Vec<String> = store
+        .list()
+        .into_iter()
+        .filter_map(|url| {
+            let rest = url.strip_prefix(&prefix)?;
+            if rest.is_empty() {
+                return None;
+            }
+            match rest.split_once('/') {
+                Some((dir, _)) if !recursive => Some(format!("{}{}/", prefix, dir)),
+                _ => Some(url),
+            }
+        })
+        .collect();
+    entries.sort();
+    entries.dedup();
+    entries
+}
+
+/// In-memory resource store: URL → version graph.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    resources: RwLock<HashMap<String, VersionGraph>>,
+}
+
+impl MemoryStore {
+    /// Create a new empty store.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl ResourceStore for MemoryStore {
+    fn append_attributed(
+        &self,
+        url: &str,
+        update: Update,
+        attribution: Option<Attribution>,
+    ) -> Result<bool> {
+        Ok(self
+            .resources
+            .write()
+            .entry(url.to_string())
+            .or_default()
+            .insert_attributed(update, attribution))
+    }
+
+    fn attribution(&self, url: &str, version_id: &str) -> Option<Attribution> {
+        self.resources
+            .read()
+            .get(url)
+            .and_then(|g| g.get(version_id)?.attribution.clone())
+    }
+
+    fn latest(&self, url: &str) -> Option<Update> {
+        self.resources
+            .read()
+            .get(url)
+            .and_then(|g| g.latest().cloned())
+    }
+
+    fn get_version(&self, url: &str, version_id: &str) -> Option<Update> {
+        self.resources
+            .read()
+            .get(url)
+            .and_then(|g| g.get(version_id).map(|node| node.update.clone()))
+    }
+
+    fn history(&self, url: &str) -> Vec<Update> {
+        self.resources
+            .read()
+            .get(url)
+            .map(|g| g.history())
+            .unwrap_or_default()
+    }
+
+    fn frontier(&self, url: &str) -> Vec<String> {
+        self.resources
+            .read()
+            .get(url)
+            .map(|g| g.frontier())
+            .unwrap_or_default()
+    }
+
+    fn graph(&self, url: &str) -> Option<VersionGraph> {
+        self.resources.read().get(url).cloned()
+    }
+
+    fn list(&self) -> Vec<String> {
+        let mut urls: Vec<String> = self.resources.read().keys().cloned().collect();
+        urls.sort();
+        urls
+    }
+}
+
+/// On-disk resource store.
+///
+/// Each resource is an append-only log of JSON-encoded updates (with their
+/// author's signature, if any), one per line, in `<dir>/<hex(url)>.log`;
+/// the version graph is rebuilt from it on open. URLs too long for a hex
+/// file name get `<dir>/h-<blake3(url)>.log` instead, whose first line
+/// records the URL. Every append is fsynced before it is acknowledged. On
+/// open, a torn final record left by a crash (one with no trailing newline)
+/// is discarded and the log truncated back to the last complete line; a
+/// complete record that doesn't parse is an error, so nothing after it is
+/// lost.
+pub struct FileStore {
+    dir: PathBuf,
+    cache: MemoryStore,
+    files: Mutex<HashMap<String, File>>,
+}
+
+impl FileStore {
+    /// Open (or create) a store rooted at `dir`, loading all existing logs.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+
+        let cache = MemoryStore::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("log") {
+                continue;
+            }
+            let stem = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default();
+            let hashed = stem.starts_with(HASHED_KEY_PREFIX);
+            let mut records = Self::load_log(&path)?.into_iter().peekable();
+            let url = match records.peek() {
+                Some(LogRecord::Header(header)) if hashed => Some(header.url.clone()),
+                _ if hashed => None,
+                _ => decode_key(stem),
+            };
+            let Some(url) = url else {
+                tracing::warn!(path = %path.display(), "skipping log with unrecognized name");
+                continue;
+            };
+            for record in records {
+                if let Some((update, attribution)) = record.into_parts() {
+                    cache.append_attributed(&url, update, attribution)?;
+                }
+            }
+        }
+
+        Ok(Self {
+            dir,
+            cache,
+            files: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Directory this store writes to.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Read every complete record from a log, truncating a torn tail.
+    fn load_log(path: &Path) -> Result<Vec<LogRecord>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut records = Vec::new();
+        let mut good_len: u64 = 0;
+        let mut line = Vec::new();
+
+        loop {
+            line.clear();
+            let n = reader.read_until(b'\n', &mut line)?;
+            if n == 0 || !line.ends_with(b"\n") {
+                break;
+            }
+            let record = serde_json::from_slice::<LogRecord>(&line).map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "{}: corrupt record at byte {}: {}",
+                        path.display(),
+                        good_len,
+                        e
+                    ),
+                )
+            })?;
+            records.push(record);
+            good_len += n as u64;
+        }
+
+        let file_len = std::fs::metadata(path)?.len();
+        if good_len < file_len {
+            tracing::warn!(
+                path = %path.display(),
+                dropped = file_len - good_len,
+                "truncating torn record at end of log"
+            );
+            let file = OpenOptions::new().write(true).open(path)?;
+            file.set_len(good_len)?;
+            file.sync_all()?;
+        }
+
+        Ok(records)
+    }
+}
+
+/// One line of a `FileStore` log. Logs written before updates were signed
+/// hold bare updates, which still load.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum LogRecord {
+    Header(LogHeader),
+    Attributed {
+        update: Update,
+        attribution: Attribution,
+    },
+    Plain(Update),
+}
+
+/// First line of a log named by hash, recording its URL.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LogHeader {
+    url: String,
+}
+
+impl LogRecord {
+    /// The update a record holds, if it isn't a header.
+    fn into_parts(self) -> Option<(Update, Option<Attribution>)> {
+        match self {
+            LogRecord::Header(_) => None,
+            LogRecord::Attributed {
+                update,
+                attribution,
+            } => Some((update, Some(attribution))),
+            LogRecord::Plain(update) => Some((update, None)),
+        }
+    }
+}
+
+impl ResourceStore for FileStore {
+    fn append_attributed(
+        &self,
+        url: &str,
+        update: Update,
+        attribution: Option<Attribution>,
+    ) -> Result<bool> {
+        // Held until the update is in the cache, so no other append can
+        // slip in between the check and the write.
+        let mut files = self.files.lock();
+        if self
+            .cache
+            .get_version(url, &version_string(&update))
+            .is_some()
+        {
+            return Ok(false);
+        }
+
+        let record = match attribution.clone() {
+            Some(attribution) => LogRecord::Attributed {
+                update: update.clone(),
+                attribution,
+            },
+            None => LogRecord::Plain(update.clone()),
+        };
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+
+        if !files.contains_key(url) {
+            let key = encode_key(url);
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.dir.join(format!("{}.log", key)))?;
+            if key.starts_with(HASHED_KEY_PREFIX) && file.metadata()?.len() == 0 {
+                let mut header = serde_json::to_vec(&LogHeader {
+                    url: url.to_string(),
+                })?;
+                header.push(b'\n');
+                file.write_all(&header)?;
+            }
+            files.insert(url.to_string(), file);
+        }
+        let file = files.get_mut(url).expect("log file opened above");
+        file.write_all(&line)?;
+        file.sync_data()?;
+
+        self.cache.append_attributed(url, update, attribution)
+    }
+
+    fn attribution(&self, url: &str, version_id: &str) -> Option<Attribution> {
+        self.cache.attribution(url, version_id)
+    }
+
+    fn latest(&self, url: &str) -> Option<Update> {
+        self.cache.latest(url)
+    }
+
+    fn get_version(&self, url: &str, version_id: &str) -> Option<Update> {
+        self.cache.get_version(url, version_id)
+    }
+
+    fn history(&self, url: &str) -> Vec<Update> {
+        self.cache.history(url)
+    }
+
+    fn frontier(&self, url: &str) -> Vec<String> {
+        self.cache.frontier(url)
+    }
+
+    fn graph(&self, url: &str) -> Option<VersionGraph> {
+        self.cache.graph(url)
+    }
+
+    fn list(&self) -> Vec<String> {
+        self.cache.list()
+    }
+
+    fn flush(&self) -> Result<()> {
+        for (_, file) in self.files.lock().drain() {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+/// Longest hex key used as a file name; with `.log` it stays under the
+/// usual 255-byte `NAME_MAX`.
+const MAX_HEX_KEY: usize = 240;
+
+/// Prefix of file names derived from a hash of the URL.
+const HASHED_KEY_PREFIX: &str = "h-";
+
+/// Hex-encode a URL into a filesystem-safe file name, or hash it if the
+/// result would be too long.
+fn encode_key(url: &str) -> String {
+    if url.len() * 2 > MAX_HEX_KEY {
+        return format!(
+            "{}{}",
+            HASHED_KEY_PREFIX,
+            blake3::hash(url.as_bytes()).to_hex()
+        );
+    }
+    url.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Inverse of `encode_key`.
+fn decode_key(name: &str) -> Option<String> {
+    if !name.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..name.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&name[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use braid_http_rs::Version;
+    use bytes::Bytes;
+
+    fn update(version: &str, body: &str) -> Update {
+        Update::snapshot(
+            Version::String(version.into()),
+            Bytes::from(body.to_string()),
+        )
+    }
+
+    #[test]
+    fn test_memory_store_history() {
+        let store = MemoryStore::new();
+        store.append("/doc", update("v1", "a")).unwrap();
+        store.append("/doc", update("v2", "b")).unwrap();
+
+        assert_eq!(store.history("/doc").len(), 2);
+        assert_eq!(version_string(&store.latest("/doc").unwrap()), "v2");
+        assert!(store.get_version("/doc", "v1").is_some());
+        assert!(store.get_version("/doc", "v3").is_none());
+        assert_eq!(store.list(), vec!["/doc".to_string()]);
+    }
+
+    #[test]
+    fn test_memory_store_frontier() {
+        let store = MemoryStore::new();
+        let mut b = update("b", "b");
+        b.parents = vec![Version::String("a".into())];
+        let mut c = update("c", "c");
+        c.parents = vec![Version::String("a".into())];
+        store.append("/doc", update("a", "a")).unwrap();
+        store.append("/doc", c).unwrap();
+        store.append("/doc", b).unwrap();
+
+        assert_eq!(store.frontier("/doc"), vec!["b", "c"]);
+        let order: Vec<String> = store.history("/doc").iter().map(version_string).collect();
+        assert_eq!(order, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_list_directory() {
+        let store = MemoryStore::new();
+        for url in ["/docs/a", "/docs/team/notes", "/docs/team/plan", "/other"] {
+            store.append(url, update("v1", "x")).unwrap();
+        }
+
+        assert_eq!(
+            list_directory(&store, "/docs/", false),
+            vec!["/docs/a", "/docs/team/"]
+        );
+        assert_eq!(
+            list_directory(&store, "/docs", true),
+            vec!["/docs/a", "/docs/team/notes", "/docs/team/plan"]
+        );
+        assert_eq!(list_directory(&store, "/", false), vec!["/docs/", "/other"]);
+        assert!(list_directory(&store, "/missing/", false).is_empty());
+    }
+
+    #[test]
+    fn test_key_encoding_roundtrip() {
+        let url = "/docs/team notes/ünïcode";
+        assert_eq!(decode_key(&encode_key(url)).as_deref(), Some(url));
+        assert_eq!(decode_key("abc"), None);
+    }
+
+    #[test]
+    fn test_file_store_persists_across_reopen() {
+        let dir = temp_dir("store");
+        {
+            let store = FileStore::open(&dir).unwrap();
+            store.append("/doc", update("v1", "a")).unwrap();
+            store.append("/doc", update("v2", "b")).unwrap();
+        }
+
+        let store = FileStore::open(&dir).unwrap();
+        assert_eq!(store.history("/doc").len(), 2);
+        assert_eq!(version_string(&store.latest("/doc").unwrap()), "v2");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_file_store_persists_attribution() {
+        let dir = temp_dir("store");
+        let key = iroh::SecretKey::from_bytes(&[7; 32]);
+        let signed = update("v2", "b");
+        let attribution = Attribution::sign(&key, "/doc", &signed).unwrap();
+        {
+            let store = FileStore::open(&dir).unwrap();
+            store.append("/doc", update("v1", "a")).unwrap();
+            store
+                .append_attributed("/doc", signed, Some(attribution.clone()))
+                .unwrap();
+        }
+
+        let store = FileStore::open(&dir).unwrap();
+        assert_eq!(store.history("/doc").len(), 2);
+        assert_eq!(store.attribution("/doc", "v1"), None);
+        assert_eq!(store.attribution("/doc", "v2"), Some(attribution));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_file_store_append_after_flush() {
+        let dir = temp_dir("store");
+        let store = FileStore::open(&dir).unwrap();
+        store.append("/doc", update("v1", "a")).unwrap();
+        store.flush().unwrap();
+        store.append("/doc", update("v2", "b")).unwrap();
+        drop(store);
+
+        let store = FileStore::open(&dir).unwrap();
+        assert_eq!(store.history("/doc").len(), 2);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_file_store_truncates_torn_record() {
+        let dir = temp_dir("store");
+        {
+            let store = FileStore::open(&dir).unwrap();
+            store.append("/doc", update("v1", "a")).unwrap();
+        }
+        let path = dir.join(format!("{}.log", encode_key("/doc")));
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"version\":[\"v2").unwrap();
+        drop(file);
+
+        let store = FileStore::open(&dir).unwrap();
+        assert_eq!(store.history("/doc").len(), 1);
+        store.append("/doc", update("v3", "c")).unwrap();
+
+        let store = FileStore::open(&dir).unwrap();
+        assert_eq!(store.history("/doc").len(), 2);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_concurrent_appends_store_a_version_once() {
+        let dir = temp_dir("store");
+        let store = Arc::new(FileStore::open(&dir).unwrap());
+        let inserted: usize = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || store.append("/doc", update("v1", "a")).unwrap())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|t| t.join().unwrap() as usize)
+            .sum();
+        assert_eq!(inserted, 1);
+        assert!(!store.append("/doc", update("v1", "a")).unwrap());
+        drop(store);
+
+        let log = std::fs::read_to_string(dir.join(format!("{}.log", encode_key("/doc")))).unwrap();
+        assert_eq!(log.lines().count(), 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_file_store_rejects_corrupt_record() {
+        let dir = temp_dir("store");
+        {
+            let store = FileStore::open(&dir).unwrap();
+            store.append("/doc", update("v1", "a")).unwrap();
+        }
+        let path = dir.join(format!("{}.log", encode_key("/doc")));
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"not json\n{\"version\":[\"v3").unwrap();
+        drop(file);
+        let len = std::fs::metadata(&path).unwrap().len();
+
+        assert!(matches!(
+            FileStore::open(&dir),
+            Err(crate::error::BraidIrohError::Storage(_))
+        ));
+        // Nothing after the corrupt line was truncated away
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_file_store_hashes_long_urls() {
+        let dir = temp_dir("store");
+        let url = format!("/docs/{}", "x".repeat(300));
+        assert!(encode_key(&url).starts_with(HASHED_KEY_PREFIX));
+        {
+            let store = FileStore::open(&dir).unwrap();
+            store.append(&url, update("v1", "a")).unwrap();
+            store.append(&url, update("v2", "b")).unwrap();
+        }
+
+        let store = FileStore::open(&dir).unwrap();
+        assert_eq!(store.list(), vec![url.clone()]);
+        assert_eq!(store.history(&url).len(), 2);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -3,45 +3,94 @@
 //! Maps resource URLs to iroh-gossip topics. When a peer PUTs an update,
 //! it gets broadcast to everyone subscribed to that URL's topic.
 //! This replaces Braid's traditional long-lived HTTP subscription responses
-//! with fully decentralized gossip.
+//! with fully decentralized gossip. Updates go out as `SignedUpdate`s,
+//! signed with the node's key. Resources with a `ResourceSecret` use a
+//! private topic and encrypted payloads instead (see `private`).
 
 use braid_http_rs::Update;
 use bytes::Bytes;
-use iroh::EndpointId;
+use iroh::{EndpointId, SecretKey};
 use iroh_gossip::api::{GossipReceiver, GossipSender, GossipTopic};
 use iroh_gossip::net::Gossip;
 use iroh_gossip::proto::TopicId;
-use std::collections::HashMap;
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+use crate::error::{BraidIrohError, Result};
+use crate::metrics::Metrics;
+use crate::private::ResourceSecret;
+use crate::signing::SignedUpdate;
+
+/// Something that happened on a joined gossip topic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopicEvent {
+    /// A peer became a direct neighbor on the topic.
+    NeighborUp(EndpointId),
+    /// A direct neighbor left the topic or disconnected.
+    NeighborDown(EndpointId),
+    /// Our receiver fell behind and gossip messages were dropped.
+    Lagged,
+}
+
+/// Callback run for every `TopicEvent` on a resource's topic, with the
+/// normalized resource URL.
+pub type TopicHook = Arc<dyn Fn(&str, &TopicEvent) + Send + Sync>;
+
+/// A joined topic: the sender used to broadcast and the neighbors seen on
+/// its receiver.
+struct TopicState {
+    sender: GossipSender,
+    neighbors: HashSet<EndpointId>,
+}
+
 /// Manages active gossip subscriptions keyed by resource URL.
 ///
 /// Each URL gets a deterministic `TopicId` via blake3 hash, so any peer
 /// that knows the URL can join the correct topic without coordination.
 pub struct SubscriptionManager {
     gossip: Gossip,
-    /// Active topics: URL → sender handle
-    topics: Arc<Mutex<HashMap<String, GossipSender>>>,
+    /// Signs every update this node broadcasts.
+    secret_key: SecretKey,
+    /// Active topics: URL → sender handle and neighbors
+    topics: Arc<Mutex<HashMap<String, TopicState>>>,
+    /// Event hooks: URL → callbacks, kept until the URL is unsubscribed
+    hooks: RwLock<HashMap<String, Vec<TopicHook>>>,
+    /// Private resources: URL → shared secret
+    secrets: RwLock<HashMap<String, ResourceSecret>>,
+    metrics: Arc<Metrics>,
 }
 
 impl SubscriptionManager {
-    /// Wrap an existing gossip instance.
-    pub fn new(gossip: Gossip) -> Self {
+    /// Wrap an existing gossip instance. `secret_key` is the node's
+    /// identity, used to sign broadcast updates.
+    pub fn new(gossip: Gossip, secret_key: SecretKey) -> Self {
+        Self::with_metrics(gossip, secret_key, Arc::new(Metrics::new()))
+    }
+
+    /// Wrap an existing gossip instance, recording traffic in `metrics`.
+    pub fn with_metrics(gossip: Gossip, secret_key: SecretKey, metrics: Arc<Metrics>) -> Self {
         Self {
             gossip,
+            secret_key,
             topics: Arc::new(Mutex::new(HashMap::new())),
+            hooks: RwLock::new(HashMap::new()),
+            secrets: RwLock::new(HashMap::new()),
+            metrics,
         }
     }
 
-    /// Normalize a URL for consistent topic lookup.
-    /// Ensures the URL starts with / and has no trailing /.
+    /// Normalize a URL for consistent topic lookup and storage keys.
+    /// Ensures the URL starts with /, has no trailing /, and has no empty
+    /// segments, so `docs//team/notes/` and `/docs/team/notes` are the
+    /// same resource.
+    ///
+    /// Collapsing empty segments changed the topic of URLs containing them
+    /// (and of the root) relative to earlier versions; see the README.
     pub fn normalize_url(url: &str) -> String {
-        if url.starts_with('/') {
-            url.trim_end_matches('/').to_string()
-        } else {
-            format!("/{}", url.trim_end_matches('/'))
-        }
+        let segments: Vec<&str> = url.split('/').filter(|s| !s.is_empty()).collect();
+        format!("/{}", segments.join("/"))
     }
 
     /// Derive a deterministic TopicId from a resource URL.
@@ -53,71 +102,238 @@ impl SubscriptionManager {
         TopicId::from_bytes(*hash.as_bytes())
     }
 
+    /// Make a resource private: its topic is derived from `secret` and its
+    /// gossip payloads are encrypted with it. Set it before subscribing; an
+    /// already joined topic keeps its topic ID until it is re-joined.
+    pub fn set_secret(&self, url: &str, secret: ResourceSecret) {
+        self.secrets.write().insert(Self::normalize_url(url), secret);
+    }
+
+    /// Make a resource public again. Returns its secret, if it had one.
+    pub fn remove_secret(&self, url: &str) -> Option<ResourceSecret> {
+        self.secrets.write().remove(&Self::normalize_url(url))
+    }
+
+    /// The shared secret of a private resource.
+    pub fn secret(&self, url: &str) -> Option<ResourceSecret> {
+        self.secrets.read().get(&Self::normalize_url(url)).cloned()
+    }
+
+    /// The topic this node uses for a resource: derived from its secret if
+    /// it is private, `topic_for_url` otherwise.
+    pub fn topic_id(&self, url: &str) -> TopicId {
+        match self.secret(url) {
+            Some(secret) => secret.topic_for_url(url),
+            None => Self::topic_for_url(url),
+        }
+    }
+
+    /// Turn a received gossip payload back into what was broadcast:
+    /// decrypted for a private resource, unchanged otherwise.
+    pub fn open_payload(&self, url: &str, payload: &[u8]) -> Result<Bytes> {
+        match self.secret(url) {
+            Some(secret) => secret.open(url, payload).map(Bytes::from),
+            None => Ok(Bytes::copy_from_slice(payload)),
+        }
+    }
+
     /// Subscribe to a resource URL. Joins the gossip topic and returns
     /// a receiver stream of incoming gossip events.
     ///
     /// `bootstrap` should contain at least one known peer so the gossip
-    /// protocol can form the initial overlay.
+    /// protocol can form the initial overlay. Whoever drains the receiver
+    /// should report neighbor and lag events through `record_event`.
     pub async fn subscribe(
         &self,
         url: &str,
         bootstrap: Vec<EndpointId>,
-    ) -> anyhow::Result<(GossipSender, GossipReceiver)> {
+    ) -> Result<(GossipSender, GossipReceiver)> {
         let normalized = Self::normalize_url(url);
-        let topic_id = Self::topic_for_url(&normalized);
-        let topic: GossipTopic = self.gossip.subscribe(topic_id, bootstrap).await?;
+        let topic_id = self.topic_id(&normalized);
+        let topic: GossipTopic = self
+            .gossip
+            .subscribe(topic_id, bootstrap)
+            .await
+            .map_err(BraidIrohError::gossip)?;
         let (sender, receiver) = topic.split();
 
         // Stash the sender so we can broadcast later
-        self.topics
-            .lock()
-            .await
-            .insert(normalized, sender.clone());
+        let state = TopicState {
+            sender: sender.clone(),
+            neighbors: HashSet::new(),
+        };
+        match self.topics.lock().await.insert(normalized, state) {
+            Some(previous) => self.forget_neighbors(&previous),
+            None => self.metrics.gossip_topics.inc(),
+        }
 
         Ok((sender, receiver))
     }
 
-    /// Broadcast a Braid Update to all peers on a resource's gossip topic.
-    /// Serializes the update to JSON bytes before sending.
-    pub async fn broadcast(&self, url: &str, update: &Update) -> anyhow::Result<()> {
+    /// Leave a resource's gossip topic and drop its event hooks. The topic
+    /// is quit once its receiver is dropped as well. Returns `false` if the
+    /// topic wasn't joined.
+    pub async fn unsubscribe(&self, url: &str) -> bool {
         let normalized = Self::normalize_url(url);
-        let bytes = serde_json::to_vec(update)?;
-        self.broadcast_raw(&normalized, Bytes::from(bytes)).await
+        self.hooks.write().remove(&normalized);
+        match self.topics.lock().await.remove(&normalized) {
+            Some(state) => {
+                self.forget_neighbors(&state);
+                self.metrics.gossip_topics.dec();
+                tracing::debug!(url = %normalized, "left topic");
+                true
+            }
+            None => false,
+        }
     }
-    
-    /// Broadcast raw bytes to all peers on a resource's gossip topic.
-    /// This allows sending wrapped messages with metadata.
-    pub async fn broadcast_raw(&self, url: &str, data: Bytes) -> anyhow::Result<()> {
-        let normalized = Self::normalize_url(url);
+
+    /// Leave every joined topic. Broadcasts hold the topic table while they
+    /// send, so this waits for in-flight broadcasts to finish first.
+    /// Returns the number of topics left.
+    pub async fn unsubscribe_all(&self) -> usize {
         let mut topics = self.topics.lock().await;
+        let count = topics.len();
+        topics.clear();
+        self.hooks.write().clear();
+        self.metrics.gossip_topics.set(0);
+        self.metrics.gossip_neighbors.set(0);
+        count
+    }
 
-        println!("[BROADCAST] url={} normalized={} topics_count={}", url, normalized, topics.len());
-        for (k, _) in topics.iter() {
-            println!("[BROADCAST]   known topic: {}", k);
+    /// Whether this node has joined the resource's topic.
+    pub async fn is_subscribed(&self, url: &str) -> bool {
+        self.topics
+            .lock()
+            .await
+            .contains_key(&Self::normalize_url(url))
+    }
+
+    /// URLs of all joined topics, sorted.
+    pub async fn active_topics(&self) -> Vec<String> {
+        let mut urls: Vec<String> = self.topics.lock().await.keys().cloned().collect();
+        urls.sort();
+        urls
+    }
+
+    /// Direct neighbors currently known on a resource's topic.
+    pub async fn neighbors(&self, url: &str) -> Result<Vec<EndpointId>> {
+        let normalized = Self::normalize_url(url);
+        match self.topics.lock().await.get(&normalized) {
+            Some(state) => Ok(state.neighbors.iter().copied().collect()),
+            None => Err(BraidIrohError::TopicNotFound(normalized)),
         }
+    }
 
-        // If we don't have a sender for this topic, join it (with no bootstrap peers)
-        // This allows us to publish to a topic we haven't explicitly subscribed to
-        if !topics.contains_key(&normalized) {
-            println!("[BROADCAST] Creating new topic for {}", normalized);
-            let topic_id = Self::topic_for_url(&normalized);
-            // Join with empty bootstrap peers since we are likely the publisher/origin
-            let topic: GossipTopic = self.gossip.subscribe(topic_id, vec![]).await?;
-            let (sender, _receiver) = topic.split();
-
-            // We discard the receiver because we don't necessarily want to listen to our own updates
-            // (or maybe we do? but for now just enable publishing)
-            topics.insert(normalized.clone(), sender);
+    /// Run `hook` for every event on a resource's topic. Hooks may be added
+    /// before subscribing and are dropped by `unsubscribe`.
+    pub fn on_event(&self, url: &str, hook: TopicHook) {
+        self.hooks
+            .write()
+            .entry(Self::normalize_url(url))
+            .or_default()
+            .push(hook);
+    }
+
+    /// Apply an event seen on a topic's receiver: track neighbors and run
+    /// the topic's hooks.
+    pub async fn record_event(&self, url: &str, event: TopicEvent) {
+        let normalized = Self::normalize_url(url);
+        {
+            let mut topics = self.topics.lock().await;
+            let Some(state) = topics.get_mut(&normalized) else {
+                return;
+            };
+            match &event {
+                TopicEvent::NeighborUp(peer) => {
+                    if state.neighbors.insert(*peer) {
+                        self.metrics.gossip_neighbors.inc();
+                    }
+                }
+                TopicEvent::NeighborDown(peer) => {
+                    if state.neighbors.remove(peer) {
+                        self.metrics.gossip_neighbors.dec();
+                    }
+                }
+                TopicEvent::Lagged => {
+                    tracing::warn!(url = %normalized, "gossip receiver lagged");
+                }
+            }
         }
 
-        if let Some(sender) = topics.get(&normalized) {
-            println!("[BROADCAST] Sending {} bytes to topic {}", data.len(), normalized);
-            sender.broadcast(data).await?;
-            println!("[BROADCAST] Broadcast complete for {}", normalized);
+        let hooks = self.hooks.read().get(&normalized).cloned().unwrap_or_default();
+        for hook in hooks {
+            hook(&normalized, &event);
         }
+    }
+
+    fn forget_neighbors(&self, state: &TopicState) {
+        self.metrics
+            .gossip_neighbors
+            .add(-(state.neighbors.len() as i64));
+    }
+
+    /// Sign an update on a resource as this node.
+    pub fn sign(&self, url: &str, update: Update) -> Result<SignedUpdate> {
+        SignedUpdate::sign(&self.secret_key, url, update)
+    }
+
+    /// Broadcast a Braid Update to all peers on a resource's gossip topic,
+    /// signed as this node. The update should already carry its version.
+    pub async fn broadcast(&self, url: &str, update: &Update) -> Result<()> {
+        let signed = self.sign(url, update.clone())?;
+        self.broadcast_signed(url, &signed).await
+    }
+
+    /// Broadcast an already signed update, serialized as JSON.
+    pub async fn broadcast_signed(&self, url: &str, signed: &SignedUpdate) -> Result<()> {
+        let normalized = Self::normalize_url(url);
+        let bytes = serde_json::to_vec(signed)?;
+        self.broadcast_raw(&normalized, Bytes::from(bytes)).await
+    }
+
+    /// Broadcast raw bytes to all peers on a resource's gossip topic.
+    /// This allows sending wrapped messages with metadata. Payloads for a
+    /// private resource are encrypted with its secret first.
+    ///
+    /// The topic must have been joined with `subscribe`; otherwise this
+    /// fails with `TopicNotFound`.
+    #[tracing::instrument(
+        name = "broadcast",
+        skip_all,
+        fields(
+            url = %Self::normalize_url(url),
+            topic = %self.topic_id(url),
+            bytes = data.len(),
+        )
+    )]
+    pub async fn broadcast_raw(&self, url: &str, data: Bytes) -> Result<()> {
+        let normalized = Self::normalize_url(url);
+        let data = match self.secret(&normalized) {
+            Some(secret) => secret.seal(&normalized, &data)?,
+            None => data,
+        };
+        let topics = self.topics.lock().await;
+        let Some(state) = topics.get(&normalized) else {
+            return Err(BraidIrohError::TopicNotFound(normalized));
+        };
+
+        let len = data.len() as u64;
+        state
+            .sender
+            .broadcast(data)
+            .await
+            .map_err(BraidIrohError::gossip)?;
+        self.metrics.gossip_updates_sent.inc();
+        self.metrics.gossip_bytes_sent.add(len);
+        tracing::debug!("broadcast sent");
         Ok(())
     }
 
+    /// Metrics recorded for this manager's gossip traffic.
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
     /// Access the underlying gossip instance (e.g. for shutdown).
     #[allow(dead_code)]
     pub fn gossip(&self) -> &Gossip {
@@ -126,14 +342,18 @@ impl SubscriptionManager {
 
     /// Join additional peers to an existing topic.
     /// This is useful for connecting to a peer after initial subscription.
-    pub async fn join_peers(&self, url: &str, peers: Vec<EndpointId>) -> anyhow::Result<()> {
+    pub async fn join_peers(&self, url: &str, peers: Vec<EndpointId>) -> Result<()> {
         let normalized = Self::normalize_url(url);
         let topics = self.topics.lock().await;
-        if let Some(sender) = topics.get(&normalized) {
-            sender.join_peers(peers).await?;
+        if let Some(state) = topics.get(&normalized) {
+            state
+                .sender
+                .join_peers(peers)
+                .await
+                .map_err(BraidIrohError::gossip)?;
             Ok(())
         } else {
-            anyhow::bail!("Topic not found: {}", normalized)
+            Err(BraidIrohError::TopicNotFound(normalized))
         }
     }
 }
@@ -178,6 +398,55 @@ mod tests {
         assert_eq!(topic_bytes.len(), 32);
     }
 
+    #[test]
+    fn test_normalize_nested_url() {
+        assert_eq!(SubscriptionManager::normalize_url("docs/team/notes/"), "/docs/team/notes");
+        assert_eq!(SubscriptionManager::normalize_url("//docs//team"), "/docs/team");
+        assert_eq!(SubscriptionManager::normalize_url("/"), "/");
+        assert_eq!(
+            SubscriptionManager::topic_for_url("/docs/team/notes"),
+            SubscriptionManager::topic_for_url("docs//team/notes/")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hooks_see_neighbors_and_unsubscribe_leaves() {
+        use crate::acl::Acl;
+        use crate::discovery::DiscoveryConfig;
+        use crate::test_util::{test_node, wait_for};
+
+        let discovery = DiscoveryConfig::mock();
+        let alice = test_node(&discovery, Acl::open()).await;
+        let bob = test_node(&discovery, Acl::open()).await;
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = events.clone();
+        alice.subscriptions().on_event(
+            "/doc",
+            Arc::new(move |url: &str, event: &TopicEvent| {
+                seen.lock().unwrap().push((url.to_string(), event.clone()));
+            }),
+        );
+        let _alice_updates = alice.subscribe("/doc", vec![]).await.unwrap();
+        let _bob_updates = bob.subscribe("/doc", vec![alice.node_id()]).await.unwrap();
+
+        let up = ("/doc".to_string(), TopicEvent::NeighborUp(bob.node_id()));
+        wait_for("bob to come up", || async { events.lock().unwrap().contains(&up) }).await;
+        bob.shutdown().await.unwrap();
+        let down = ("/doc".to_string(), TopicEvent::NeighborDown(bob.node_id()));
+        wait_for("bob to go down", || async { events.lock().unwrap().contains(&down) }).await;
+
+        let subscriptions = alice.subscriptions();
+        assert!(subscriptions.unsubscribe("/doc").await);
+        assert!(!subscriptions.active_topics().await.contains(&"/doc".to_string()));
+        assert!(matches!(
+            subscriptions.broadcast_raw("/doc", Bytes::from_static(b"x")).await,
+            Err(BraidIrohError::TopicNotFound(url)) if url == "/doc"
+        ));
+        assert!(!subscriptions.unsubscribe("/doc").await);
+
+        alice.shutdown().await.unwrap();
+    }
+
     #[test]
     fn test_topic_derivation_special_chars() {
         let url = "/resource/with spaces/and/special/chars/!@#$%";
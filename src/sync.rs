@@ -0,0 +1,255 @@
+//! Catch-up sync for late joiners.
+//!
+//! Gossip only carries updates broadcast while a peer is listening. When a
+//! node meets a neighbor on a resource's topic (or receives an update whose
+//! parents it has never seen) it asks that neighbor for its version list
+//! over the regular Braid-HTTP/3 routes and fetches whatever it is missing:
+//!
+//! - `GET <url>?history=true` → the neighbor's version IDs, parents first
+//! - `GET <url>?version=<id>` with `Accept: application/braid+json` → one
+//!   stored update as JSON
+//!
+//! A round fetches at most `MAX_VERSIONS_PER_ROUND` versions, parents
+//! first; whatever is left is picked up by the next round with that peer.
+//! The node runs at most one round per resource and peer at a time (see
+//! `CatchUpSlots`), however often neighbors come and go.
+//!
+//! A fetched version must come with `Braid-Author` / `Braid-Signature`
+//! headers, which are verified before it is stored, just as gossip only
+//! carries signed updates. One that is unsigned, whose signature doesn't
+//! check out, or whose author may not write the resource, is skipped.
+
+use braid_http_rs::Update;
+use http::{HeaderMap, HeaderValue, Method, StatusCode};
+use iroh::EndpointId;
+use iroh_h3_client::IrohH3Client;
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::codec;
+use crate::error::{BraidIrohError, Result};
+use crate::protocol::BraidAppState;
+use crate::signing::{Attribution, AUTHOR_HEADER, SIGNATURE_HEADER};
+
+/// Most versions fetched from a peer in one catch-up round.
+pub const MAX_VERSIONS_PER_ROUND: usize = 256;
+
+/// Fetch the versions of `url` that `peer` has and we don't, up to
+/// `MAX_VERSIONS_PER_ROUND`, and ingest them in the peer's (topological)
+/// order.
+///
+/// Returns the number of updates added to the local store.
+pub async fn catch_up(
+    client: &IrohH3Client,
+    state: &BraidAppState,
+    peer: EndpointId,
+    url: &str,
+) -> Result<usize> {
+    catch_up_round(client, state, peer, url, MAX_VERSIONS_PER_ROUND).await
+}
+
+async fn catch_up_round(
+    client: &IrohH3Client,
+    state: &BraidAppState,
+    peer: EndpointId,
+    url: &str,
+    limit: usize,
+) -> Result<usize> {
+    let Some((_, body)) = fetch(client, peer, url, ("history", "true")).await? else {
+        return Ok(0);
+    };
+    let versions: Vec<String> = serde_json::from_slice(&body)?;
+
+    let mut missing: Vec<String> = versions
+        .into_iter()
+        .filter(|v| state.store.get_version(url, v).is_none())
+        .collect();
+    if missing.is_empty() {
+        return Ok(0);
+    }
+
+    tracing::debug!(url = %url, peer = %peer, missing = missing.len(), "catching up");
+    if missing.len() > limit {
+        let left = missing.len() - limit;
+        tracing::debug!(url = %url, peer = %peer, left, "leaving versions for a later round");
+        missing.truncate(limit);
+    }
+
+    let mut added = 0;
+    for version in missing {
+        let Some((headers, body)) = fetch(client, peer, url, ("version", &version)).await? else {
+            tracing::warn!(url = %url, peer = %peer, version = %version, "peer no longer has version");
+            continue;
+        };
+        let update: Update = serde_json::from_slice(&body)?;
+        let attribution = match verified_attribution(&headers, url, &update) {
+            Ok(attribution) => attribution,
+            Err(e) => {
+                tracing::warn!(url = %url, peer = %peer, version = %version, "skipping version: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = state.authorize_write(Some(&attribution.author), url, &update) {
+            tracing::warn!(url = %url, peer = %peer, version = %version, "skipping version: {}", e);
+            continue;
+        }
+        if state.ingest_attributed(url, update, Some(attribution))? {
+            added += 1;
+        }
+    }
+
+    tracing::info!(url = %url, peer = %peer, added, "caught up with peer");
+    Ok(added)
+}
+
+/// Catch-up rounds in progress, by resource URL and peer.
+#[derive(Clone, Default)]
+pub(crate) struct CatchUpSlots {
+    running: Arc<Mutex<HashSet<(String, EndpointId)>>>,
+}
+
+impl CatchUpSlots {
+    /// Reserve the round for `url` and `peer`, unless one is running. The
+    /// slot is released when the returned guard is dropped.
+    pub(crate) fn claim(&self, url: &str, peer: EndpointId) -> Option<CatchUpSlot> {
+        let key = (url.to_string(), peer);
+        if !self.running.lock().insert(key.clone()) {
+            return None;
+        }
+        Some(CatchUpSlot {
+            slots: self.clone(),
+            key,
+        })
+    }
+}
+
+/// A claimed catch-up slot; see `CatchUpSlots::claim`.
+pub(crate) struct CatchUpSlot {
+    slots: CatchUpSlots,
+    key: (String, EndpointId),
+}
+
+impl Drop for CatchUpSlot {
+    fn drop(&mut self) {
+        self.slots.running.lock().remove(&self.key);
+    }
+}
+
+/// The author a peer claims for a fetched version, checked against the
+/// update. An unsigned version is a `BadSignature` error.
+fn verified_attribution(headers: &HeaderMap, url: &str, update: &Update) -> Result<Attribution> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let (Some(author), Some(signature)) = (header(AUTHOR_HEADER), header(SIGNATURE_HEADER)) else {
+        return Err(BraidIrohError::BadSignature("unsigned version".to_string()));
+    };
+    let attribution = Attribution::from_headers(author, signature)?;
+    attribution.verify(url, update)?;
+    Ok(attribution)
+}
+
+/// GET `url?<name>=<value>` from `peer`. `None` if the peer answers 404.
+async fn fetch(
+    client: &IrohH3Client,
+    peer: EndpointId,
+    url: &str,
+    query: (&str, &str),
+) -> Result<Option<(HeaderMap, bytes::Bytes)>> {
+    let target = format!("https://{}{}", peer, codec::resource_uri(url, &[query]));
+    let resp = client
+        .request(Method::GET, &target)
+        .header(
+            http::header::ACCEPT,
+            HeaderValue::from_static(codec::BRAID_JSON),
+        )
+        .build()
+        .map_err(BraidIrohError::peer)?
+        .send()
+        .await
+        .map_err(BraidIrohError::peer)?;
+
+    if resp.status == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp.status.is_success() {
+        return Err(BraidIrohError::Peer(format!(
+            "GET {} returned {}",
+            target, resp.status
+        )));
+    }
+    let headers = resp.headers.clone();
+    Ok(Some((headers, resp.bytes().await.map_err(BraidIrohError::peer)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::Acl;
+    use crate::discovery::DiscoveryConfig;
+    use crate::node::BRAID_H3_ALPN;
+    use crate::test_util::test_node;
+    use braid_http_rs::Version;
+    use bytes::Bytes;
+
+    #[tokio::test]
+    async fn test_catch_up_skips_unsigned_versions() {
+        let discovery = DiscoveryConfig::mock();
+        let neighbor = test_node(&discovery, Acl::open()).await;
+        let node = test_node(&discovery, Acl::open()).await;
+
+        // Stored without an author, so served without a signature.
+        let unsigned = Update::snapshot(Version::String("1".into()), Bytes::from("forged"));
+        neighbor.store_update("/doc", unsigned).await.unwrap();
+        let signed = Update::snapshot(Version::String("2".into()), Bytes::from("real"));
+        neighbor.put("/doc", signed).await.unwrap();
+
+        let client = IrohH3Client::new(node.endpoint().clone(), BRAID_H3_ALPN.to_vec());
+        let state = node.app_state();
+        let added = catch_up(&client, state, neighbor.node_id(), "/doc").await.unwrap();
+
+        assert_eq!(added, 1);
+        assert!(node.get_version("/doc", "1").await.is_none());
+        assert!(node.get_version("/doc", "2").await.is_some());
+
+        node.shutdown().await.unwrap();
+        neighbor.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_rounds_are_capped() {
+        let discovery = DiscoveryConfig::mock();
+        let neighbor = test_node(&discovery, Acl::open()).await;
+        let node = test_node(&discovery, Acl::open()).await;
+        for version in ["1", "2", "3"] {
+            let update = Update::snapshot(Version::String(version.into()), Bytes::from(version));
+            neighbor.put("/doc", update).await.unwrap();
+        }
+
+        let client = IrohH3Client::new(node.endpoint().clone(), BRAID_H3_ALPN.to_vec());
+        let (state, peer) = (node.app_state(), neighbor.node_id());
+        assert_eq!(catch_up_round(&client, state, peer, "/doc", 2).await.unwrap(), 2);
+        assert!(node.get_version("/doc", "2").await.is_some());
+        assert!(node.get_version("/doc", "3").await.is_none());
+        assert_eq!(catch_up_round(&client, state, peer, "/doc", 2).await.unwrap(), 1);
+        assert!(node.get_version("/doc", "3").await.is_some());
+
+        node.shutdown().await.unwrap();
+        neighbor.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn test_one_catch_up_per_resource_and_peer() {
+        let slots = CatchUpSlots::default();
+        let (alice, bob) = (
+            iroh::SecretKey::from_bytes(&[1; 32]).public(),
+            iroh::SecretKey::from_bytes(&[2; 32]).public(),
+        );
+
+        let first = slots.claim("/doc", alice).unwrap();
+        assert!(slots.claim("/doc", alice).is_none());
+        assert!(slots.claim("/doc", bob).is_some());
+        assert!(slots.claim("/other", alice).is_some());
+        drop(first);
+        assert!(slots.claim("/doc", alice).is_some());
+    }
+}
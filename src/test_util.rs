@@ -0,0 +1,60 @@
+//! Fixtures shared by the unit tests.
+
+use http::{Method, StatusCode};
+use iroh::{EndpointId, RelayMode};
+use iroh_h3_client::IrohH3Client;
+use std::path::PathBuf;
+
+use crate::acl::Acl;
+use crate::discovery::DiscoveryConfig;
+use crate::node::{BraidIrohNode, BRAID_H3_ALPN};
+
+/// A fresh, not yet created directory under the system temp dir, unique to
+/// the caller. Tests remove it when done.
+pub(crate) fn temp_dir(label: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("braid-iroh-{}-{}", label, uuid::Uuid::new_v4()))
+}
+
+/// A node on loopback only: mock `discovery`, no relays, in-memory storage.
+pub(crate) async fn test_node(discovery: &DiscoveryConfig, acl: Acl) -> BraidIrohNode {
+    BraidIrohNode::builder()
+        .discovery(discovery.clone())
+        .relay_mode(RelayMode::Disabled)
+        .acl(acl)
+        .spawn()
+        .await
+        .expect("test node spawns")
+}
+
+/// GET `path` from `server` over HTTP/3, as `client`.
+pub(crate) async fn h3_get(client: &BraidIrohNode, server: EndpointId, path: &str) -> StatusCode {
+    IrohH3Client::new(client.endpoint().clone(), BRAID_H3_ALPN.to_vec())
+        .request(Method::GET, format!("https://{}{}", server, path))
+        .build()
+        .expect("valid request")
+        .send()
+        .await
+        .expect("server answers")
+        .status
+}
+
+/// Poll `check` until it holds, failing the test with `what` after ten
+/// seconds. For conditions reached over gossip, which has no completion
+/// signal to wait on.
+pub(crate) async fn wait_for<F, Fut>(what: &str, mut check: F)
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    let polling = async {
+        while !check().await {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+    };
+    if tokio::time::timeout(std::time::Duration::from_secs(10), polling)
+        .await
+        .is_err()
+    {
+        panic!("timed out waiting for {}", what);
+    }
+}
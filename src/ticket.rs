@@ -0,0 +1,235 @@
+//! Capability tickets.
+//!
+//! A `BraidTicket` is everything another peer needs to join a resource: its
+//! URL and gossip topic, the addresses of peers to bootstrap from and, for a
+//! private resource, its `ResourceSecret`. It may also record the right the
+//! issuer granted the recipient in its ACL.
+//!
+//! Tickets print as `braid` followed by the lowercase base32 encoding of
+//! their JSON form, so they survive copy/paste and fit in a QR code. A
+//! ticket with a secret is a bearer capability: whoever holds it can read
+//! the resource's gossip.
+
+use iroh::{EndpointAddr, EndpointId};
+use iroh_gossip::proto::TopicId;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::acl::Right;
+use crate::error::{BraidIrohError, Result};
+use crate::private::ResourceSecret;
+use crate::signing::{hex_decode, hex_encode};
+use crate::subscription::SubscriptionManager;
+
+/// Prefix of a ticket's string form.
+pub const TICKET_PREFIX: &str = "braid";
+
+/// An invitation to a resource.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BraidTicket {
+    /// Normalized resource URL.
+    pub url: String,
+    /// The resource's gossip topic.
+    #[serde(with = "topic_hex")]
+    pub topic: TopicId,
+    /// Peers to join the topic through and fetch history from.
+    pub bootstrap: Vec<EndpointAddr>,
+    /// The secret of a private resource.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<ResourceSecret>,
+    /// The right the issuer granted the recipient, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right: Option<Right>,
+}
+
+impl BraidTicket {
+    /// A ticket for a public resource.
+    pub fn new(url: &str, bootstrap: Vec<EndpointAddr>) -> Self {
+        let url = SubscriptionManager::normalize_url(url);
+        Self {
+            topic: SubscriptionManager::topic_for_url(&url),
+            url,
+            bootstrap,
+            secret: None,
+            right: None,
+        }
+    }
+
+    /// Make this a ticket for a private resource.
+    pub fn with_secret(mut self, secret: ResourceSecret) -> Self {
+        self.topic = secret.topic_for_url(&self.url);
+        self.secret = Some(secret);
+        self
+    }
+
+    /// Record the right granted to the recipient.
+    pub fn with_right(mut self, right: Right) -> Self {
+        self.right = Some(right);
+        self
+    }
+
+    /// IDs of the bootstrap peers.
+    pub fn peers(&self) -> Vec<EndpointId> {
+        self.bootstrap.iter().map(|addr| addr.id).collect()
+    }
+
+    /// Check that the topic is the one the URL and secret lead to, so a
+    /// redeemed ticket can't silently join some other topic.
+    pub fn validate(&self) -> Result<()> {
+        let expected = match &self.secret {
+            Some(secret) => secret.topic_for_url(&self.url),
+            None => SubscriptionManager::topic_for_url(&self.url),
+        };
+        if self.topic != expected {
+            return Err(BraidIrohError::Ticket(format!(
+                "topic does not match {}",
+                self.url
+            )));
+        }
+        if self.bootstrap.is_empty() {
+            return Err(BraidIrohError::Ticket("no bootstrap peers".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for BraidTicket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_vec(self).map_err(|_| fmt::Error)?;
+        write!(f, "{}{}", TICKET_PREFIX, base32_encode(&json))
+    }
+}
+
+impl FromStr for BraidTicket {
+    type Err = BraidIrohError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let encoded = s
+            .trim()
+            .strip_prefix(TICKET_PREFIX)
+            .ok_or_else(|| BraidIrohError::Ticket(format!("missing `{}` prefix", TICKET_PREFIX)))?;
+        let json = base32_decode(encoded)
+            .ok_or_else(|| BraidIrohError::Ticket("invalid base32".to_string()))?;
+        let ticket: BraidTicket =
+            serde_json::from_slice(&json).map_err(|e| BraidIrohError::Ticket(e.to_string()))?;
+        ticket.validate()?;
+        Ok(ticket)
+    }
+}
+
+mod topic_hex {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        topic: &TopicId,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        hex_encode(topic.as_bytes()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<TopicId, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        let bytes: [u8; 32] = hex_decode(&hex)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| serde::de::Error::custom("topic must be 64 hex digits"))?;
+        Ok(TopicId::from_bytes(bytes))
+    }
+}
+
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// RFC 4648 base32, lowercase, without padding.
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+/// Inverse of `base32_encode`; also accepts uppercase, as QR scanners
+/// often produce it.
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a == c.to_ascii_lowercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(seed: u8) -> EndpointAddr {
+        EndpointAddr::new(iroh::SecretKey::from_bytes(&[seed; 32]).public())
+    }
+
+    #[test]
+    fn test_base32_roundtrip() {
+        for len in 0..12 {
+            let bytes: Vec<u8> = (0..len).map(|i: u32| (i * 37 + 1) as u8).collect();
+            let encoded = base32_encode(&bytes);
+            assert_eq!(base32_decode(&encoded).unwrap(), bytes);
+            assert_eq!(base32_decode(&encoded.to_uppercase()).unwrap(), bytes);
+        }
+        assert_eq!(base32_encode(b"foobar"), "mzxw6ytboi");
+        assert!(base32_decode("not base32!").is_none());
+    }
+
+    #[test]
+    fn test_ticket_string_roundtrip() {
+        let ticket = BraidTicket::new("docs/notes/", vec![addr(1)])
+            .with_secret(ResourceSecret::generate())
+            .with_right(Right::Write);
+        let encoded = ticket.to_string();
+        assert!(encoded.starts_with(TICKET_PREFIX));
+
+        let parsed: BraidTicket = encoded.parse().unwrap();
+        assert_eq!(parsed, ticket);
+        assert_eq!(parsed.url, "/docs/notes");
+        assert_eq!(parsed.peers(), vec![addr(1).id]);
+    }
+
+    #[test]
+    fn test_ticket_topic_must_match() {
+        let public = BraidTicket::new("/doc", vec![addr(1)]);
+        assert!(public.validate().is_ok());
+
+        let mut forged = public.clone();
+        forged.topic = SubscriptionManager::topic_for_url("/other");
+        assert!(matches!(
+            forged.to_string().parse::<BraidTicket>(),
+            Err(BraidIrohError::Ticket(_))
+        ));
+
+        let mut stripped = public.with_secret(ResourceSecret::generate());
+        stripped.secret = None;
+        assert!(stripped.validate().is_err());
+        assert!(BraidTicket::new("/doc", vec![]).validate().is_err());
+        assert!("ticket123".parse::<BraidTicket>().is_err());
+    }
+}